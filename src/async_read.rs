@@ -0,0 +1,265 @@
+use std::{
+    io::{self, Cursor, Read, Seek, SeekFrom},
+    mem,
+};
+
+use binrw::{BinRead, BinReaderExt, BinResult, Endian};
+use futures_util::{stream, Stream};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+
+use crate::{
+    binrw_types::{Buckets, Record, RecordOffset, RecordSpace, U32orU64},
+    compression::Compression,
+    decode_value,
+    tree::{self, KeySearch},
+    Error, KeyWithHash, Result, TCHDB,
+};
+
+/// Large enough for most records except their values
+const INITIAL_READ_SIZE: usize = 512;
+
+/// A reader for the async API of `TCHDB`.
+///
+/// As binrw can't parse async readers, bytes are read into a buffer before being parsed.
+/// The buffer is extended until the whole structure is read.
+#[derive(Debug)]
+pub struct AsyncReader<R>(R);
+
+impl<R> AsyncReader<R> {
+    pub fn new(reader: R) -> Self {
+        AsyncReader(reader)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.0
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncReader<R> {
+    /// Read at most `len` bytes from `pos`, which are shorter only at the end of file
+    pub(crate) async fn read_at(&mut self, pos: u64, len: usize) -> io::Result<Vec<u8>> {
+        self.0.seek(SeekFrom::Start(pos)).await?;
+        let mut buf = Vec::with_capacity(len);
+        (&mut self.0).take(len as u64).read_to_end(&mut buf).await?;
+        Ok(buf)
+    }
+
+    /// The length of the file
+    pub(crate) async fn len(&mut self) -> io::Result<u64> {
+        self.0.seek(SeekFrom::End(0)).await
+    }
+
+    pub(crate) async fn parse_at<T, A>(&mut self, pos: u64, endian: Endian, args: A) -> BinResult<T>
+    where
+        T: for<'a> BinRead<Args<'a> = A>,
+        A: Clone,
+    {
+        let mut len = INITIAL_READ_SIZE;
+        loop {
+            let buf = self.read_at(pos, len).await?;
+            let end_of_file = buf.len() < len;
+            match OffsetCursor::new(pos, buf).read_type_args(endian, args.clone()) {
+                Err(e) if !end_of_file && is_short(&e) => len *= 2,
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Whether the error is caused by the end of the buffer
+fn is_short(e: &binrw::Error) -> bool {
+    match e {
+        // the variants whose magic doesn't match fail regardless of the buffer
+        binrw::Error::EnumErrors { variant_errors, .. } => {
+            variant_errors.iter().any(|(_, e)| is_short(e))
+        }
+        binrw::Error::Backtrace(bt) => is_short(&bt.error),
+        e => e.is_eof(),
+    }
+}
+
+/// A cursor on bytes read from `base`, whose positions are the ones in the file
+struct OffsetCursor {
+    base: u64,
+    inner: Cursor<Vec<u8>>,
+}
+
+impl OffsetCursor {
+    fn new(base: u64, buf: Vec<u8>) -> Self {
+        OffsetCursor {
+            base,
+            inner: Cursor::new(buf),
+        }
+    }
+}
+
+impl Read for OffsetCursor {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Read::read(&mut self.inner, buf)
+    }
+}
+
+impl Seek for OffsetCursor {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => {
+                SeekFrom::Start(pos.checked_sub(self.base).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "invalid seek before the buffer",
+                    )
+                })?)
+            }
+            pos => pos,
+        };
+        Ok(self.base + Seek::seek(&mut self.inner, pos)?)
+    }
+}
+
+/// The async counterparts of lookups and iterations
+impl<U: U32orU64, R: AsyncRead + AsyncSeek + Unpin> TCHDB<U, AsyncReader<R>> {
+    pub async fn read_buckets(&mut self) -> Result<Buckets<U>> {
+        self.reader
+            .parse_at(
+                self.bucket_offset,
+                self.endian,
+                (self.header.bucket_number,),
+            )
+            .await
+            .map_err(Error::CorruptBucketArray)
+    }
+
+    async fn read_bucket(&mut self, idx: u64) -> Result<RecordOffset<U>> {
+        let pos = self.bucket_offset + mem::size_of::<U>() as u64 * idx;
+        self.reader
+            .parse_at(pos, self.endian, ())
+            .await
+            .map_err(Error::CorruptBucketArray)
+    }
+
+    async fn read_record_space_at(
+        &mut self,
+        offset: u64,
+        read_value: bool,
+    ) -> Result<RecordSpace<U>> {
+        self.reader
+            .parse_at(
+                offset,
+                self.endian,
+                (offset, read_value, self.header.options.compression()),
+            )
+            .await
+            .map_err(Error::corrupt_record(offset))
+    }
+
+    pub async fn get_record(&mut self, key: &KeyWithHash<'_>) -> Result<Option<Record<U>>> {
+        let (found, mut log) = self.get_record_detail(key).await?;
+        if found {
+            Ok(log.pop())
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn get_record_detail(
+        &mut self,
+        key: &KeyWithHash<'_>,
+    ) -> Result<(bool, Vec<Record<U>>)> {
+        let bucket = self.read_bucket(key.idx).await?;
+
+        let mut search = KeySearch::new(key, bucket, self.header.alignment_power);
+        while let Some(offset) = search.next_offset()? {
+            let record_space = self.read_record_space_at(offset, false).await?;
+            if search.visit(tree::expect_record(offset, record_space)?) {
+                return Ok((true, search.into_records()));
+            }
+        }
+
+        Ok((false, search.into_records()))
+    }
+
+    pub async fn read_value(&mut self, record: &mut Record<U>) -> Result<()> {
+        if record.value.is_read() {
+            return Ok(());
+        }
+
+        let offset = record.value_offset();
+        let size = record.value_size.0 as u64;
+        // don't allocate a buffer for a corrupt size beyond the end of file
+        if offset + size > self.reader.len().await? {
+            return Err(Error::CorruptRecord {
+                offset: record.space_offset(),
+                source: binrw::Error::AssertFail {
+                    pos: offset,
+                    message: format!("value size {} exceeds the end of file", size),
+                },
+            });
+        }
+        let buf = self.reader.read_at(offset, size as usize).await?;
+        record
+            .value
+            .read_value(&mut OffsetCursor::new(offset, buf))
+            .map_err(Error::corrupt_record(record.space_offset()))?;
+        if self.header.options.compression() == Compression::External {
+            decode_value(self.codec.as_deref(), record)?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn get<K: AsRef<[u8]>>(&mut self, key: K) -> Result<Option<Vec<u8>>> {
+        let key = self.hash(key.as_ref());
+        match self.get_record(&key).await? {
+            None => Ok(None),
+            Some(mut record) => {
+                self.read_value(&mut record).await?;
+                let value = record.value.into_value();
+                Ok(Some(value.into_value()))
+            }
+        }
+    }
+
+    pub async fn get_detail<'a, K: AsRef<[u8]> + ?Sized>(
+        &mut self,
+        key: &'a K,
+    ) -> Result<(KeyWithHash<'a>, bool, Vec<Record<U>>)> {
+        let key = self.hash(key.as_ref());
+        let (found, visited_records) = self.get_record_detail(&key).await?;
+        Ok((key, found, visited_records))
+    }
+
+    /// A stream version of `read_record_spaces`
+    pub fn read_record_spaces(
+        &mut self,
+        pv: bool,
+    ) -> impl Stream<Item = Result<RecordSpace<U>>> + '_ {
+        let first_record = self.header.first_record;
+        stream::unfold((self, first_record), move |(tchdb, pos)| async move {
+            if pos >= tchdb.header.file_size {
+                return None;
+            }
+
+            let result = tchdb.read_next(pos, pv).await;
+            let next_pos = match &result {
+                Ok(RecordSpace::Record(record)) => record.next_record(),
+                Ok(RecordSpace::FreeBlock(free_block)) => pos + free_block.block_size as u64,
+                // stop iterating, the position of the next record is unknown
+                Err(_) => tchdb.header.file_size,
+            };
+            Some((result, (tchdb, next_pos)))
+        })
+    }
+
+    async fn read_next(&mut self, pos: u64, pv: bool) -> Result<RecordSpace<U>> {
+        match self.read_record_space_at(pos, pv).await? {
+            RecordSpace::Record(mut record) => {
+                if pv && self.header.options.compression() == Compression::External {
+                    decode_value(self.codec.as_deref(), &mut record)?;
+                }
+                Ok(RecordSpace::Record(record))
+            }
+            free_block => Ok(free_block),
+        }
+    }
+}
@@ -1,48 +1,203 @@
+mod free_block_pool;
 mod lazy_load;
 mod record;
 mod vnum;
 
-use std::fmt::Debug;
+use std::{
+    fmt::{self, Debug},
+    str,
+};
 
-use binrw::BinRead;
+use binrw::{binrw, BinRead, BinWrite};
 use num_traits::int::PrimInt;
 
-pub use self::record::Record;
+use crate::compression::Compression;
 
-use vnum::VNum;
+pub use self::free_block_pool::{FreeBlockPool, FreeBlockPoolElement};
+pub use self::record::{Record, RecordValue};
 
 /// u32 or u64 value
-pub trait U32orU64: BinRead<Args<'static> = ()> + Debug + PrimInt + 'static {}
+pub trait U32orU64:
+    BinRead<Args<'static> = ()> + for<'a> BinWrite<Args<'a> = ()> + Debug + PrimInt + 'static
+{
+}
 
-impl<T> U32orU64 for T where T: BinRead<Args<'static> = ()> + Debug + PrimInt + 'static {}
+impl<T> U32orU64 for T where
+    T: BinRead<Args<'static> = ()> + for<'a> BinWrite<Args<'a> = ()> + Debug + PrimInt + 'static
+{
+}
 
-#[derive(BinRead, Debug)]
+/// The beginning of the magic number, followed by the versions
+pub const MAGIC_DATA: &[u8] = b"ToKyO CaBiNeT\n";
+
+#[derive(BinRead, BinWrite, Debug)]
 pub struct Header {
-    #[br(count = 32, assert(magic_number.starts_with(b"ToKyO CaBiNeT")))]
+    #[br(count = 32)]
     pub magic_number: Vec<u8>,
-    #[br(assert(database_type == 0))]
+    /// `None` if the magic number has no versions
+    #[br(calc = Version::parse(&magic_number))]
+    #[bw(ignore)]
+    pub version: Option<Version>,
     pub database_type: u8,
-    pub additional_flags: u8,
+    #[br(map = AdditionalFlags::from_bits)]
+    #[bw(map = AdditionalFlags::bits)]
+    pub additional_flags: AdditionalFlags,
     pub alignment_power: u8,
     pub free_block_pool_power: u8,
-    #[br(pad_after = 3)]
-    pub options: u8,
+    #[br(map = Options::from_bits)]
+    #[bw(map = Options::bits)]
+    #[brw(pad_after = 3)]
+    pub options: Options,
     pub bucket_number: u64,
     pub record_number: u64,
     pub file_size: u64,
-    #[br(pad_after = 56)]
+    #[brw(pad_after = 56)]
     pub first_record: u64,
     #[br(count = 128)]
     pub opaque_region: Vec<u8>,
 }
 
-#[derive(BinRead, Clone, Copy, Debug)]
+/// The versions written in the magic number, such as `1.0:911`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Version {
+    /// The version of the file format as `(major, minor)`
+    pub format: (u32, u32),
+    /// The version number of the library which created the database
+    pub library: u32,
+}
+
+impl Version {
+    /// The only version of the format which tokyo cabinet has ever written
+    pub const SUPPORTED_FORMAT: (u32, u32) = (1, 0);
+
+    /// Parse the line following `MAGIC_DATA`
+    pub fn parse(magic_number: &[u8]) -> Option<Self> {
+        let rest = magic_number.strip_prefix(MAGIC_DATA)?;
+        let line = &rest[..rest.iter().position(|&b| b == b'\n')?];
+        let (format, library) = str::from_utf8(line).ok()?.split_once(':')?;
+        let (major, minor) = format.split_once('.')?;
+
+        Some(Version {
+            format: (major.parse().ok()?, minor.parse().ok()?),
+            library: library.parse().ok()?,
+        })
+    }
+
+    #[inline]
+    pub fn is_supported(&self) -> bool {
+        self.format == Self::SUPPORTED_FORMAT
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}:{}", self.format.0, self.format.1, self.library)
+    }
+}
+
+/// Flags which tokyo cabinet sets on the state of the database
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AdditionalFlags {
+    /// The database is opened by a writer, or wasn't closed cleanly (`HDBFOPEN`)
+    pub open: bool,
+    /// A writer hit a fatal error (`HDBFFATAL`)
+    pub fatal: bool,
+}
+
+impl AdditionalFlags {
+    pub fn from_bits(bits: u8) -> Self {
+        AdditionalFlags {
+            open: bits & 0x01 != 0,
+            fatal: bits & 0x02 != 0,
+        }
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.open as u8 | (self.fatal as u8) << 1
+    }
+}
+
+/// The original bytes of a region, which are logged in the write-ahead log before a
+/// transaction modifies the region
+#[binrw]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalEntry {
+    pub offset: u64,
+    #[br(temp)]
+    #[bw(calc = data.len() as u32)]
+    size: u32,
+    #[br(count = size)]
+    pub data: Vec<u8>,
+}
+
+/// Options of the database, which are set by `tchdbtune`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    /// The size of the bucket array can exceed 2GB (`HDBTLARGE`)
+    pub large: bool,
+    /// Values are compressed with deflate (`HDBTDEFLATE`)
+    pub deflate: bool,
+    /// Values are compressed with bzip2 (`HDBTBZIP`)
+    pub bzip: bool,
+    /// Values are compressed with TCBS (`HDBTTCBS`)
+    pub tcbs: bool,
+    /// Values are encoded with an external codec (`HDBTEXCODEC`)
+    pub excodec: bool,
+}
+
+impl Options {
+    pub fn from_bits(bits: u8) -> Self {
+        Options {
+            large: bits & 0x01 != 0,
+            deflate: bits & 0x02 != 0,
+            bzip: bits & 0x04 != 0,
+            tcbs: bits & 0x08 != 0,
+            excodec: bits & 0x10 != 0,
+        }
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.large as u8
+            | (self.deflate as u8) << 1
+            | (self.bzip as u8) << 2
+            | (self.tcbs as u8) << 3
+            | (self.excodec as u8) << 4
+    }
+
+    /// The compression applied to values, in the same precedence as tokyo cabinet
+    pub fn compression(&self) -> Compression {
+        if self.deflate {
+            Compression::Deflate
+        } else if self.bzip {
+            Compression::Bzip2
+        } else if self.tcbs {
+            Compression::Tcbs
+        } else if self.excodec {
+            Compression::External
+        } else {
+            Compression::None
+        }
+    }
+}
+
+#[derive(BinRead, BinWrite, Clone, Copy, Debug)]
 
 pub struct RecordOffset<U: U32orU64> {
     value: U,
 }
 
 impl<U: U32orU64> RecordOffset<U> {
+    /// Returns `None` if the offset can't be represented by `U`
+    #[inline]
+    pub fn new(offset: u64, alignment_power: u8) -> Option<Self> {
+        U::from(offset >> alignment_power).map(|value| RecordOffset { value })
+    }
+
+    #[inline]
+    pub fn empty() -> Self {
+        RecordOffset { value: U::zero() }
+    }
+
     #[inline]
     pub fn offset(&self, alignment_power: u8) -> u64 {
         self.value.to_u64().unwrap() << alignment_power
@@ -58,25 +213,22 @@ impl<U: U32orU64> RecordOffset<U> {
 #[br(import(bucket_number: u64))]
 pub struct Buckets<U: U32orU64>(#[br(count = bucket_number)] pub Vec<RecordOffset<U>>);
 
-#[derive(BinRead, Debug)]
-pub struct FreeBlockPoolElement {
-    pub offset: VNum<u32>, // TODO: recorded as the difference of the former free block and as the quotient by the alignment
-    pub size: VNum<u32>,
-}
-
-#[derive(BinRead, Debug)]
+#[derive(BinRead, BinWrite, Debug)]
 pub struct FreeBlock {
+    #[br(assert(block_size >= 5, "free block size {} is less than its header", block_size))]
     pub block_size: u32,
+    // only the header is written, the rest of the block is left as it is
     #[br(count = block_size - 5)]
+    #[bw(ignore)]
     pub padding: Vec<u8>,
 }
 
-#[derive(BinRead, Debug)]
-#[br(import(offset: u64, read_value: bool))]
+#[derive(BinRead, BinWrite, Debug)]
+#[br(import(offset: u64, read_value: bool, compression: Compression))]
 pub enum RecordSpace<U: U32orU64> {
-    #[br(magic = 0xc8u8)]
+    #[brw(magic = 0xc8u8)]
     // add the length of magic to the offset
-    Record(#[br(args(offset + 1, read_value))] Record<U>),
-    #[br(magic = 0xb0u8)]
+    Record(#[br(args(offset + 1, read_value, compression))] Record<U>),
+    #[brw(magic = 0xb0u8)]
     FreeBlock(FreeBlock),
 }
@@ -0,0 +1,85 @@
+use std::io::{Read, Seek, Write};
+
+use binrw::{BinRead, BinResult, BinWrite, Endian};
+
+use super::vnum::VNum;
+
+/// An element of the free block pool, in bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FreeBlockPoolElement {
+    /// The absolute offset of the free block
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// The free block pool, which is placed between the bucket array and the first record.
+///
+/// In the file, each element is recorded as a pair of variable length numbers divided by
+/// the alignment: the difference of the offset from the former element, and the size.
+/// The pool is terminated by a pair of zeros or by its capacity `2^free_block_pool_power`.
+#[derive(Clone, Debug, Default)]
+pub struct FreeBlockPool(pub Vec<FreeBlockPoolElement>);
+
+impl BinRead for FreeBlockPool {
+    /// The alignment power and the capacity of the pool
+    type Args<'a> = (u8, usize);
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        endian: Endian,
+        (alignment_power, capacity): Self::Args<'_>,
+    ) -> BinResult<Self> {
+        let mut elements = Vec::new();
+        let mut offset = 0;
+        while elements.len() < capacity {
+            let diff = <VNum<u64>>::read_options(reader, endian, ())?;
+            let size = <VNum<u32>>::read_options(reader, endian, ())?;
+            if diff.0 == 0 && size.0 == 0 {
+                break;
+            }
+
+            offset += diff.0 << alignment_power;
+            elements.push(FreeBlockPoolElement {
+                offset,
+                size: (size.0 as u64) << alignment_power,
+            });
+        }
+
+        Ok(FreeBlockPool(elements))
+    }
+}
+
+impl BinWrite for FreeBlockPool {
+    /// The alignment power and the size of the region for the pool.
+    /// Elements are sorted by offsets, and ones which exceed the region are dropped.
+    type Args<'a> = (u8, u64);
+
+    fn write_options<W: Write + Seek>(
+        &self,
+        writer: &mut W,
+        endian: Endian,
+        (alignment_power, region_size): Self::Args<'_>,
+    ) -> BinResult<()> {
+        let mut elements = self.0.clone();
+        elements.sort_unstable();
+
+        let mut buf = Vec::new();
+        let mut cursor = binrw::io::Cursor::new(&mut buf);
+        let mut base = 0;
+        for elem in elements {
+            let pos = cursor.position();
+            VNum((elem.offset - base) >> alignment_power).write_options(&mut cursor, endian, ())?;
+            VNum((elem.size >> alignment_power) as u32).write_options(&mut cursor, endian, ())?;
+            // 2 bytes for the terminator
+            if cursor.position() + 2 > region_size {
+                buf.truncate(pos as usize);
+                break;
+            }
+            base = elem.offset;
+        }
+        buf.extend_from_slice(&[0, 0]);
+
+        writer.write_all(&buf)?;
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{Read, Seek, SeekFrom, Write};
 
-use binrw::{BinRead, BinResult, Endian, NamedArgs};
+use binrw::{BinRead, BinResult, BinWrite, Endian, NamedArgs};
 
 #[derive(NamedArgs, Clone)]
 pub struct LazyArgs<Inner> {
@@ -45,23 +45,51 @@ where
     }
 }
 
+impl<T, A> BinWrite for Lazy<T, A>
+where
+    for<'a> T: BinWrite<Args<'a> = ()>,
+{
+    type Args<'a> = ();
+
+    fn write_options<W: Write + Seek>(
+        &self,
+        writer: &mut W,
+        endian: Endian,
+        args: Self::Args<'_>,
+    ) -> BinResult<()> {
+        match self {
+            Lazy::Read(value) => value.write_options(writer, endian, args),
+            Lazy::Unread { .. } => Err(binrw::Error::AssertFail {
+                pos: writer.stream_position()?,
+                message: "must read the value before writing".to_string(),
+            }),
+        }
+    }
+}
+
 impl<T, A> Lazy<T, A>
 where
     for<'a> T: BinRead<Args<'a> = A>,
     for<'a> T::Args<'a>: Default,
     A: Copy,
 {
-    pub fn read_value<R: Read + Seek>(&mut self, reader: &mut R) {
+    pub fn read_value<R: Read + Seek>(&mut self, reader: &mut R) -> BinResult<()> {
         if let Lazy::Unread {
             offset,
             endian,
             args,
         } = self
         {
-            reader.seek(SeekFrom::Start(*offset)).unwrap();
-            let value = <T>::read_options(reader, *endian, *args).unwrap();
+            reader.seek(SeekFrom::Start(*offset))?;
+            let value = <T>::read_options(reader, *endian, *args)?;
             *self = Lazy::Read(value);
         }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn is_read(&self) -> bool {
+        matches!(self, Lazy::Read(_))
     }
 
     pub fn into_value(self) -> T {
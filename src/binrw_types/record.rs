@@ -1,16 +1,22 @@
-use std::mem;
+use std::{
+    io::{self, Read, Seek, Write},
+    mem,
+};
 
-use binrw::BinRead;
+use binrw::{BinRead, BinResult, BinWrite, Endian};
+
+use crate::compression::{Compression, ValueCodec};
 
 use super::lazy_load::Lazy;
 use super::vnum::VNum;
 
 use super::{RecordOffset, U32orU64};
 
-#[derive(BinRead, Debug)]
-#[br(import(offset: u64, read_value: bool))]
+#[derive(BinRead, BinWrite, Debug)]
+#[br(import(offset: u64, read_value: bool, compression: Compression))]
 pub struct Record<U: U32orU64> {
     #[br(calc = offset)]
+    #[bw(ignore)]
     pub offset: u64,
     pub hash_value: u8,
     pub left_chain: RecordOffset<U>,
@@ -20,11 +26,51 @@ pub struct Record<U: U32orU64> {
     pub value_size: VNum<u32>,
     #[br(count = key_size.0)]
     pub key: Vec<u8>,
-    #[br(args {lazy: !read_value, inner: (value_size.0,)})]
-    pub value: Lazy<RecordValue, (u32,)>,
+    #[br(args {lazy: !read_value, inner: (value_size.0, compression)})]
+    pub value: Lazy<RecordValue, (u32, Compression)>,
 }
 
 impl<U: U32orU64> Record<U> {
+    /// Create a record to be written at `space_offset`, whose value is already encoded
+    pub(crate) fn new(
+        space_offset: u64,
+        hash_value: u8,
+        left_chain: RecordOffset<U>,
+        right_chain: RecordOffset<U>,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    ) -> Self {
+        Record {
+            offset: space_offset + 1,
+            hash_value,
+            left_chain,
+            right_chain,
+            padding_size: 0,
+            key_size: VNum(key.len() as u32),
+            value_size: VNum(value.len() as u32),
+            key,
+            value: Lazy::Read(RecordValue(value)),
+        }
+    }
+
+    /// The offset of the magic number of the record
+    #[inline]
+    pub fn space_offset(&self) -> u64 {
+        self.offset - 1
+    }
+
+    /// The size of the record including the magic number and the padding
+    #[inline]
+    pub fn space_size(&self) -> u64 {
+        self.next_record() - self.space_offset()
+    }
+
+    /// The offset of the value, which is encoded if the database is compressed
+    #[inline]
+    pub fn value_offset(&self) -> u64 {
+        self.next_record() - self.padding_size as u64 - self.value_size.0 as u64
+    }
+
     #[inline]
     pub fn next_record(&self) -> u64 {
         self.offset
@@ -37,11 +83,59 @@ impl<U: U32orU64> Record<U> {
             + self.value_size.0 as u64
             + self.padding_size as u64
     }
+
+    /// Decode the value with an external codec if the value has been read
+    pub(crate) fn decode_value(&mut self, codec: &dyn ValueCodec) -> io::Result<()> {
+        if let Lazy::Read(value) = &mut self.value {
+            value.0 = codec.decode(&value.0)?;
+        }
+        Ok(())
+    }
 }
 
-#[derive(BinRead, Debug)]
-#[br(import(count: u32))]
-pub struct RecordValue(#[br(count = count)] Vec<u8>);
+/// The value of a record, decompressed if the database is compressed
+#[derive(Debug)]
+pub struct RecordValue(Vec<u8>);
+
+impl BinRead for RecordValue {
+    type Args<'a> = (u32, Compression);
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        endian: Endian,
+        (count, compression): Self::Args<'_>,
+    ) -> BinResult<Self> {
+        let pos = reader.stream_position()?;
+        // don't allocate the whole size in advance, which may be corrupt
+        let mut data = Vec::new();
+        reader.by_ref().take(count as u64).read_to_end(&mut data)?;
+        if data.len() < count as usize {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        match compression.decompress(&data, endian) {
+            Ok(value) => Ok(RecordValue(value)),
+            Err(err) => Err(binrw::Error::Custom {
+                pos,
+                err: Box::new(err),
+            }),
+        }
+    }
+}
+
+impl BinWrite for RecordValue {
+    type Args<'a> = ();
+
+    /// Write the value as it is, encoding is the responsibility of the caller
+    fn write_options<W: Write + Seek>(
+        &self,
+        writer: &mut W,
+        endian: Endian,
+        args: Self::Args<'_>,
+    ) -> BinResult<()> {
+        self.0.write_options(writer, endian, args)
+    }
+}
 
 impl RecordValue {
     #[inline]
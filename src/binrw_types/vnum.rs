@@ -1,9 +1,10 @@
 use std::{
-    io::{Read, Seek},
-    ops::{Add, AddAssign, Mul, ShlAssign, ShrAssign, Sub},
+    io::{Read, Seek, Write},
+    ops::{ShrAssign, Sub},
 };
 
-use binrw::{BinRead, BinResult, Endian};
+use binrw::{BinRead, BinResult, BinWrite, Endian};
+use num_traits::{CheckedAdd, CheckedMul};
 
 #[derive(Debug)]
 pub struct VNum<T>(pub T);
@@ -28,7 +29,7 @@ where
 
 impl<T> BinRead for VNum<T>
 where
-    T: From<u8> + Ord + Add + Mul<Output = T> + Sub<Output = T> + ShlAssign<i32> + AddAssign + Copy,
+    T: From<u8> + Ord + Sub<Output = T> + CheckedAdd + CheckedMul + Copy,
 {
     type Args<'a> = ();
 
@@ -37,19 +38,56 @@ where
         endian: Endian,
         args: Self::Args<'_>,
     ) -> BinResult<Self> {
+        let pos = reader.stream_position()?;
+        let overflow = || binrw::Error::AssertFail {
+            pos,
+            message: "variable length number overflows".to_string(),
+        };
+
         let mut value = T::from(0);
         let mut base = T::from(1);
 
         loop {
             let x = T::from(<u8>::read_options(reader, endian, args)?);
             if x < T::from(0x80) {
-                value += x * base;
+                value = x
+                    .checked_mul(&base)
+                    .and_then(|x| value.checked_add(&x))
+                    .ok_or_else(overflow)?;
                 break;
             }
-            value += base * (T::from(0xFF) - x);
-            base <<= 7;
+            value = (T::from(0xFF) - x)
+                .checked_mul(&base)
+                .and_then(|x| value.checked_add(&x))
+                .ok_or_else(overflow)?;
+            base = base.checked_mul(&T::from(0x80)).ok_or_else(overflow)?;
         }
 
         Ok(VNum(value))
     }
 }
+
+impl<T> BinWrite for VNum<T>
+where
+    T: Into<u64> + Copy,
+{
+    type Args<'a> = ();
+
+    fn write_options<W: Write + Seek>(
+        &self,
+        writer: &mut W,
+        endian: Endian,
+        args: Self::Args<'_>,
+    ) -> BinResult<()> {
+        let mut value: u64 = self.0.into();
+
+        loop {
+            let x = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                return x.write_options(writer, endian, args);
+            }
+            (0xFF - x).write_options(writer, endian, args)?;
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    mem,
+    path::Path,
+};
+
+use binrw::{BinWriterExt, Endian};
+
+use crate::{
+    binrw_types::{AdditionalFlags, Header, Options, Version, MAGIC_DATA},
+    load::{self, TCHDBLoaded},
+    Error, Result,
+};
+
+/// The version of tokyo cabinet 1.4.48, the latest release
+const VERSION: Version = Version {
+    format: Version::SUPPORTED_FORMAT,
+    library: 911,
+};
+
+pub(crate) const DEFAULT_BUCKET_NUMBER: u64 = 131071;
+const DEFAULT_ALIGNMENT_POWER: u8 = 4;
+const DEFAULT_FREE_BLOCK_POOL_POWER: u8 = 10;
+pub(crate) const MAX_ALIGNMENT_POWER: u8 = 16;
+pub(crate) const MAX_FREE_BLOCK_POOL_POWER: u8 = 20;
+
+/// Sizes of the free block pool, the base region and each element
+const FREE_BLOCK_POOL_BASE_SIZE: u64 = 64;
+const FREE_BLOCK_POOL_ELEMENT_SIZE: u64 = 4;
+
+/// Create a new database with the parameters of `tchdbtune`
+#[derive(Clone, Debug)]
+pub struct TCHDBBuilder {
+    endian: Endian,
+    bucket_number: u64,
+    alignment_power: u8,
+    free_block_pool_power: u8,
+    options: Options,
+}
+
+impl Default for TCHDBBuilder {
+    fn default() -> Self {
+        TCHDBBuilder {
+            endian: Endian::Little,
+            bucket_number: DEFAULT_BUCKET_NUMBER,
+            alignment_power: DEFAULT_ALIGNMENT_POWER,
+            free_block_pool_power: DEFAULT_FREE_BLOCK_POOL_POWER,
+            options: Options::default(),
+        }
+    }
+}
+
+impl TCHDBBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn endian(mut self, endian: Endian) -> Self {
+        self.endian = endian;
+        self
+    }
+
+    /// The number is rounded up to a prime number as `tchdbtune` does, and 0 means the default
+    pub fn bucket_number(mut self, bucket_number: u64) -> Self {
+        self.bucket_number = if bucket_number > 0 {
+            get_prime(bucket_number)
+        } else {
+            DEFAULT_BUCKET_NUMBER
+        };
+        self
+    }
+
+    pub fn alignment_power(mut self, alignment_power: u8) -> Self {
+        self.alignment_power = alignment_power.min(MAX_ALIGNMENT_POWER);
+        self
+    }
+
+    pub fn free_block_pool_power(mut self, free_block_pool_power: u8) -> Self {
+        self.free_block_pool_power = free_block_pool_power.min(MAX_FREE_BLOCK_POOL_POWER);
+        self
+    }
+
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Create a database file, truncating the file if it exists
+    pub fn create<T>(&self, path: T) -> Result<TCHDBLoaded<File>>
+    where
+        T: AsRef<Path>,
+    {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        Ok(self.build(file)?.path(path))
+    }
+
+    /// Write an empty database from the beginning of `writer`
+    pub fn build<W: Read + Write + Seek>(&self, mut writer: W) -> Result<TCHDBLoaded<W>> {
+        let bucket_size = if self.options.large {
+            mem::size_of::<u64>()
+        } else {
+            mem::size_of::<u32>()
+        } as u64;
+        let free_block_pool_size = FREE_BLOCK_POOL_BASE_SIZE
+            + (FREE_BLOCK_POOL_ELEMENT_SIZE << self.free_block_pool_power);
+        let align = 1 << self.alignment_power;
+        let first_record = self
+            .bucket_number
+            .checked_mul(bucket_size)
+            .and_then(|size| size.checked_add(256 + free_block_pool_size))
+            .and_then(|end| end.checked_next_multiple_of(align))
+            // records must be addressable by 32-bit offsets without the large option
+            .filter(|&offset| self.options.large || offset <= u32::MAX as u64)
+            .ok_or(Error::BucketNumberOverflow {
+                bucket_number: self.bucket_number,
+            })?;
+
+        let mut magic_number = MAGIC_DATA.to_vec();
+        magic_number.extend_from_slice(format!("{}\n", VERSION).as_bytes());
+        magic_number.resize(32, 0);
+        let header = Header {
+            magic_number,
+            version: Some(VERSION),
+            database_type: 0,
+            additional_flags: AdditionalFlags::default(),
+            alignment_power: self.alignment_power,
+            free_block_pool_power: self.free_block_pool_power,
+            options: self.options,
+            bucket_number: self.bucket_number,
+            record_number: 0,
+            file_size: first_record,
+            first_record,
+            opaque_region: vec![0; 128],
+        };
+
+        writer.seek(SeekFrom::Start(0))?;
+        writer
+            .write_type(&header, self.endian)
+            .map_err(Error::write_failed)?;
+        // the empty bucket array and free block pool
+        io::copy(&mut io::repeat(0).take(first_record - 256), &mut writer)?;
+        writer.flush()?;
+
+        load::load_with_endian(writer, self.endian)
+    }
+}
+
+/// The prime numbers of `tcgetprime`, which grow by about 8% each
+const PRIMES: [u64; 256] = [
+    1, 2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 79, 83, 89, 97,
+    103, 109, 113, 127, 137, 139, 149, 157, 167, 179, 193, 199, 211, 227, 241, 257, 277, 293, 313,
+    337, 359, 383, 409, 439, 467, 503, 541, 577, 619, 661, 709, 761, 823, 887, 953, 1031, 1109,
+    1193, 1289, 1381, 1493, 1613, 1741, 1879, 2029, 2179, 2357, 2549, 2753, 2971, 3209, 3469, 3739,
+    4027, 4349, 4703, 5087, 5503, 5953, 6427, 6949, 7517, 8123, 8783, 9497, 10273, 11113, 12011,
+    12983, 14033, 15173, 16411, 17749, 19183, 20753, 22447, 24281, 26267, 28411, 30727, 33223,
+    35933, 38873, 42043, 45481, 49201, 53201, 57557, 62233, 67307, 72817, 78779, 85229, 92203,
+    99733, 107897, 116731, 126271, 136607, 147793, 159871, 172933, 187091, 202409, 218971, 236897,
+    256279, 277261, 299951, 324503, 351061, 379787, 410857, 444487, 480881, 520241, 562841, 608903,
+    658753, 712697, 771049, 834181, 902483, 976369, 1056323, 1142821, 1236397, 1337629, 1447153,
+    1565659, 1693859, 1832561, 1982627, 2144977, 2320627, 2510653, 2716249, 2938679, 3179303,
+    3439651, 3721303, 4026031, 4355707, 4712381, 5098259, 5515729, 5967347, 6456007, 6984629,
+    7556579, 8175383, 8844859, 9569143, 10352717, 11200489, 12117689, 13109983, 14183539, 15345007,
+    16601593, 17961079, 19431899, 21023161, 22744717, 24607243, 26622317, 28802401, 31160981,
+    33712729, 36473443, 39460231, 42691603, 46187573, 49969847, 54061849, 58488943, 63278561,
+    68460391, 74066549, 80131819, 86693767, 93793069, 101473717, 109783337, 118773397, 128499677,
+    139022417, 150406843, 162723577, 176048909, 190465427, 206062531, 222936881, 241193053,
+    260944219, 282312799, 305431229, 330442829, 357502601, 386778277, 418451333, 452718089,
+    489790921, 529899637, 573292817, 620239453, 671030513, 725980837, 785430967, 849749479,
+    919334987, 994618837, 1076067617, 1164186217, 1259520799, 1362662261, 1474249943, 1594975441,
+    1725587117, 1866894511, 2019773507, 2185171673, 2364114217, 2557710269, 2767159799, 2993761039,
+    3238918481, 3504151727, 3791104843, 4101556399, 4294967291,
+];
+
+/// Round `n` up to a prime number in the table as `tcgetprime` does, or the largest one if `n`
+/// is beyond the table
+fn get_prime(n: u64) -> u64 {
+    PRIMES
+        .iter()
+        .copied()
+        .find(|&prime| n <= prime)
+        .unwrap_or(PRIMES[PRIMES.len() - 1])
+}
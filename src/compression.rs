@@ -0,0 +1,68 @@
+mod tcbs;
+
+use std::io::{self, Read, Write};
+
+use binrw::Endian;
+use bzip2::{read::BzDecoder, write::BzEncoder};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder};
+
+/// A codec for databases with `HDBTEXCODEC`, which corresponds to
+/// the functions registered by `tchdbsetcodecfunc`.
+/// Codecs must be thread-safe, as a database can be shared between threads.
+pub trait ValueCodec: Send + Sync {
+    fn encode(&self, value: &[u8]) -> io::Result<Vec<u8>>;
+    fn decode(&self, value: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+/// The algorithm used to compress record values
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    /// Raw deflate (`HDBTDEFLATE`)
+    Deflate,
+    /// bzip2 (`HDBTBZIP`)
+    Bzip2,
+    /// BWT encoding of tokyo cabinet (`HDBTTCBS`)
+    Tcbs,
+    /// Encoded by an external codec (`HDBTEXCODEC`)
+    External,
+}
+
+impl Compression {
+    /// Compress a value to be stored in the database.
+    /// Values to be encoded by an external codec are returned as they are.
+    pub fn compress(&self, value: &[u8], endian: Endian) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::None | Compression::External => Ok(value.to_vec()),
+            Compression::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(value)?;
+                encoder.finish()
+            }
+            Compression::Bzip2 => {
+                let mut encoder = BzEncoder::new(Vec::new(), bzip2::Compression::best());
+                encoder.write_all(value)?;
+                encoder.finish()
+            }
+            Compression::Tcbs => Ok(tcbs::encode(value, endian)),
+        }
+    }
+
+    /// Decompress a value as stored in the database.
+    /// Values encoded by an external codec are returned as they are.
+    pub fn decompress(&self, data: &[u8], endian: Endian) -> io::Result<Vec<u8>> {
+        let mut value = Vec::new();
+        match self {
+            Compression::None | Compression::External => value.extend_from_slice(data),
+            Compression::Deflate => {
+                DeflateDecoder::new(data).read_to_end(&mut value)?;
+            }
+            Compression::Bzip2 => {
+                BzDecoder::new(data).read_to_end(&mut value)?;
+            }
+            Compression::Tcbs => value = tcbs::decode(data, endian)?,
+        }
+        Ok(value)
+    }
+}
@@ -0,0 +1,204 @@
+//! The "TCBS" codec of tokyo cabinet, which is the combination of
+//! BWT, move-to-front and Elias gamma encoding
+
+use std::io;
+
+use binrw::Endian;
+
+/// Values are split into units of this size before BWT
+const UNIT_SIZE: usize = 8192;
+
+pub fn encode(value: &[u8], endian: Endian) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(value.len() + value.len() / UNIT_SIZE * 2 + 2);
+    for unit in value.chunks(UNIT_SIZE) {
+        let idx_pos = buf.len();
+        buf.extend_from_slice(&[0, 0]);
+        let idx = bwt_encode(unit, &mut buf) as u16;
+        let idx = match endian {
+            Endian::Big => idx.to_be_bytes(),
+            Endian::Little => idx.to_le_bytes(),
+        };
+        buf[idx_pos..idx_pos + 2].copy_from_slice(&idx);
+    }
+
+    mtf_encode(&mut buf);
+    gamma_encode(&buf)
+}
+
+pub fn decode(data: &[u8], endian: Endian) -> io::Result<Vec<u8>> {
+    let mut buf = gamma_decode(data)?;
+    mtf_decode(&mut buf);
+
+    let mut value = Vec::with_capacity(buf.len());
+    let mut rest = &buf[..];
+    while !rest.is_empty() {
+        if rest.len() < 2 {
+            return Err(invalid_data("truncated BWT unit"));
+        }
+        let idx = [rest[0], rest[1]];
+        let idx = match endian {
+            Endian::Big => u16::from_be_bytes(idx),
+            Endian::Little => u16::from_le_bytes(idx),
+        } as usize;
+        let size = UNIT_SIZE.min(rest.len() - 2);
+        let unit = &rest[2..2 + size];
+        if idx >= size {
+            return Err(invalid_data("BWT index out of range"));
+        }
+        bwt_decode(unit, idx, &mut value);
+        rest = &rest[2 + size..];
+    }
+
+    Ok(value)
+}
+
+/// Append the last column of the sorted rotations of `unit` to `out`
+/// and return the row of the original string
+fn bwt_encode(unit: &[u8], out: &mut Vec<u8>) -> usize {
+    let size = unit.len();
+    let doubled = [unit, unit].concat();
+    let mut rows: Vec<usize> = (0..size).collect();
+    rows.sort_by(|&a, &b| doubled[a..a + size].cmp(&doubled[b..b + size]));
+
+    let mut idx = 0;
+    for (row, &start) in rows.iter().enumerate() {
+        if start == 0 {
+            idx = row;
+            out.push(unit[size - 1]);
+        } else {
+            out.push(unit[start - 1]);
+        }
+    }
+    idx
+}
+
+/// Invert BWT, `last` is the last column of the sorted rotations
+/// and `idx` is the row of the original string
+fn bwt_decode(last: &[u8], idx: usize, out: &mut Vec<u8>) {
+    let mut counts = [0usize; 0x100];
+    for &c in last {
+        counts[c as usize] += 1;
+    }
+    let mut starts = [0usize; 0x100];
+    let mut sum = 0;
+    for (start, count) in starts.iter_mut().zip(counts) {
+        *start = sum;
+        sum += count;
+    }
+
+    // next[i] is the row of the rotation which starts one after the rotation of row i
+    let mut next = vec![0; last.len()];
+    for (i, &c) in last.iter().enumerate() {
+        next[starts[c as usize]] = i;
+        starts[c as usize] += 1;
+    }
+
+    let mut row = idx;
+    for _ in 0..last.len() {
+        row = next[row];
+        out.push(last[row]);
+    }
+}
+
+fn mtf_encode(buf: &mut [u8]) {
+    let mut table: [u8; 0x100] = std::array::from_fn(|i| i as u8);
+    for b in buf.iter_mut() {
+        let c = *b;
+        let idx = table.iter().position(|&t| t == c).unwrap();
+        table.copy_within(0..idx, 1);
+        table[0] = c;
+        *b = idx as u8;
+    }
+}
+
+fn mtf_decode(buf: &mut [u8]) {
+    let mut table: [u8; 0x100] = std::array::from_fn(|i| i as u8);
+    for b in buf.iter_mut() {
+        let idx = *b as usize;
+        let c = table[idx];
+        table.copy_within(0..idx, 1);
+        table[0] = c;
+        *b = c;
+    }
+}
+
+fn gamma_encode(buf: &[u8]) -> Vec<u8> {
+    // the first 3 bits are reserved for the number of bits used in the last byte
+    let mut out = vec![0u8];
+    let mut bit_pos = 3;
+    let mut push = |bit: bool| {
+        if bit_pos == 8 {
+            out.push(0);
+            bit_pos = 0;
+        }
+        if bit {
+            *out.last_mut().unwrap() |= 1 << bit_pos;
+        }
+        bit_pos += 1;
+    };
+
+    for &b in buf {
+        if b == 0 {
+            push(true);
+            continue;
+        }
+
+        let c = b as u32 + 1;
+        let prefix_len = 31 - c.leading_zeros();
+        for _ in 0..prefix_len {
+            push(false);
+        }
+        for i in (0..=prefix_len).rev() {
+            push(c & (1 << i) != 0);
+        }
+    }
+
+    if bit_pos == 8 {
+        out.push(0);
+        bit_pos = 0;
+    }
+    out[0] |= bit_pos & 7;
+    out
+}
+
+fn gamma_decode(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    if data.is_empty() {
+        return Ok(out);
+    }
+
+    // the lowest 3 bits of the first byte hold the number of bits used in the last byte
+    let bit_num = ((data.len() - 1) * 8 + (data[0] & 7) as usize)
+        .checked_sub(3)
+        .ok_or_else(|| invalid_data("truncated gamma code"))?;
+    let mut bits = (3..bit_num + 3).map(|i| (data[i / 8] >> (i % 8)) & 1 == 1);
+
+    while let Some(bit) = bits.next() {
+        if bit {
+            out.push(0);
+            continue;
+        }
+
+        let mut prefix_len = 1;
+        for bit in bits.by_ref() {
+            if bit {
+                break;
+            }
+            prefix_len += 1;
+        }
+        let mut c = 1u32;
+        for _ in 0..prefix_len {
+            match bits.next() {
+                Some(bit) => c = (c << 1) | bit as u32,
+                None => break,
+            }
+        }
+        out.push((c - 1) as u8);
+    }
+
+    Ok(out)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,120 @@
+//! Text representations of binary keys and values
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::{Error, Result};
+
+/// Decode hexadecimal digits, which may be separated by whitespaces as `tchmgr -sx` accepts
+pub fn decode_hex(s: &str) -> Result<Vec<u8>> {
+    let digits = s
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .map(|c| {
+            c.to_digit(16)
+                .map(|d| d as u8)
+                .ok_or_else(|| Error::InvalidEncoding(format!("invalid hex digit {:?}", c)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if digits.len() % 2 != 0 {
+        return Err(Error::InvalidEncoding(
+            "odd number of hex digits".to_string(),
+        ));
+    }
+
+    Ok(digits.chunks(2).map(|d| d[0] << 4 | d[1]).collect())
+}
+
+pub fn decode_base64(s: &str) -> Result<Vec<u8>> {
+    STANDARD
+        .decode(s.trim())
+        .map_err(|e| Error::InvalidEncoding(format!("invalid base64: {}", e)))
+}
+
+/// Decode escape sequences of C string literals, such as `\t`, `\x7f` and `\0`
+pub fn unescape(s: &str) -> Result<Vec<u8>> {
+    let mut bytes = s.bytes().peekable();
+    let mut decoded = Vec::with_capacity(s.len());
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            decoded.push(b);
+            continue;
+        }
+
+        let b = match bytes.next() {
+            Some(b'a') => 0x07,
+            Some(b'b') => 0x08,
+            Some(b't') => b'\t',
+            Some(b'n') => b'\n',
+            Some(b'v') => 0x0b,
+            Some(b'f') => 0x0c,
+            Some(b'r') => b'\r',
+            Some(b'e') => 0x1b,
+            Some(b @ (b'\\' | b'\'' | b'"' | b'?')) => b,
+            Some(b'x') => {
+                let mut value = None;
+                // at most 2 digits not to overflow a byte
+                for _ in 0..2 {
+                    match bytes.peek().and_then(|&d| (d as char).to_digit(16)) {
+                        Some(d) => {
+                            value = Some(value.unwrap_or(0) << 4 | d as u8);
+                            bytes.next();
+                        }
+                        None => break,
+                    }
+                }
+                value.ok_or_else(|| {
+                    Error::InvalidEncoding("\\x is not followed by hex digits".to_string())
+                })?
+            }
+            Some(d @ b'0'..=b'7') => {
+                let mut value = (d - b'0') as u32;
+                for _ in 0..2 {
+                    match bytes.peek() {
+                        Some(&d @ b'0'..=b'7') => {
+                            value = value << 3 | (d - b'0') as u32;
+                            bytes.next();
+                        }
+                        _ => break,
+                    }
+                }
+                u8::try_from(value).map_err(|_| {
+                    Error::InvalidEncoding(format!("octal escape \\{:o} exceeds a byte", value))
+                })?
+            }
+            Some(b) => {
+                return Err(Error::InvalidEncoding(format!(
+                    "unknown escape sequence \\{}",
+                    b as char
+                )))
+            }
+            None => return Err(Error::InvalidEncoding("trailing backslash".to_string())),
+        };
+        decoded.push(b);
+    }
+
+    Ok(decoded)
+}
+
+pub fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn encode_base64(data: &[u8]) -> String {
+    STANDARD.encode(data)
+}
+
+/// Escape bytes other than printable ASCII characters, which `unescape` decodes
+pub fn escape(data: &[u8]) -> String {
+    let mut escaped = String::with_capacity(data.len());
+    for &b in data {
+        match b {
+            b'\\' => escaped.push_str("\\\\"),
+            b'\t' => escaped.push_str("\\t"),
+            b'\n' => escaped.push_str("\\n"),
+            b'\r' => escaped.push_str("\\r"),
+            0x20..=0x7e => escaped.push(b as char),
+            b => escaped.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    escaped
+}
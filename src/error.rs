@@ -0,0 +1,245 @@
+use std::{error, fmt, io, result};
+
+use crate::binrw_types::Version;
+
+/// Errors occurred while reading a database
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    CorruptHeader(binrw::Error),
+    BadMagic,
+    /// The format version in the magic number is unknown, or `None` if missing
+    UnsupportedVersion(Option<Version>),
+    UnsupportedDatabaseType(u8),
+    /// A parameter in the header is out of the range which tokyo cabinet writes
+    InvalidHeader {
+        field: &'static str,
+        value: u64,
+    },
+    CorruptBucketArray(binrw::Error),
+    CorruptFreeBlockPool(binrw::Error),
+    CorruptWal(binrw::Error),
+    CorruptRecord {
+        offset: u64,
+        source: binrw::Error,
+    },
+    UnexpectedFreeBlock {
+        offset: u64,
+    },
+    /// The record at `offset` is linked again while following chains
+    ChainCycle {
+        offset: u64,
+    },
+    MissingCodec,
+    Codec {
+        offset: u64,
+        source: io::Error,
+    },
+    OffsetOverflow {
+        offset: u64,
+    },
+    /// The bucket array of a new database is beyond the offsets which it can address
+    BucketNumberOverflow {
+        bucket_number: u64,
+    },
+    /// A key or a value given as text can't be decoded
+    InvalidEncoding(String),
+    /// A line of records to import can't be parsed
+    InvalidImport {
+        line: u64,
+        reason: String,
+    },
+    /// The database may be torn, which is an error only in the strict mode
+    Unclean(Vec<Warning>),
+    /// A writer holds the lock and `LockMode::NonBlocking` is given
+    Locked,
+    /// A transaction is begun while another one is in progress
+    NestedTransaction,
+    /// A transaction is committed or aborted without beginning it
+    NoTransaction,
+    /// A transaction is begun on a database not opened by `load::open_writable` nor
+    /// `TCHDBBuilder::create`, whose log has no path
+    UnknownPath,
+}
+
+/// A problem of the database which doesn't prevent reading it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Warning {
+    /// `HDBFOPEN` is set
+    NotClosed,
+    /// `HDBFFATAL` is set
+    FatalError,
+    /// The write-ahead log of an interrupted transaction is left
+    PendingWal,
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl Error {
+    #[inline]
+    pub(crate) fn corrupt_record(offset: u64) -> impl FnOnce(binrw::Error) -> Self {
+        move |source| Error::CorruptRecord { offset, source }
+    }
+
+    /// Errors in writing are only caused by the underlying writer
+    pub(crate) fn write_failed(e: binrw::Error) -> Self {
+        match e {
+            binrw::Error::Io(e) => Error::Io(e),
+            binrw::Error::Backtrace(bt) => Error::write_failed(*bt.error),
+            e => Error::Io(io::Error::other(reason(&e))),
+        }
+    }
+
+    /// The offset in the file where the error occurred, if known
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            Error::CorruptRecord { offset, .. }
+            | Error::UnexpectedFreeBlock { offset }
+            | Error::ChainCycle { offset }
+            | Error::Codec { offset, .. }
+            | Error::OffsetOverflow { offset } => Some(*offset),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::CorruptHeader(e) => write!(f, "corrupt header: {}", reason(e)),
+            Error::BadMagic => write!(f, "bad magic number, not a tokyo cabinet database"),
+            Error::UnsupportedVersion(Some(version)) => write!(
+                f,
+                "unsupported format version: {} (only {}.{} is supported)",
+                version,
+                Version::SUPPORTED_FORMAT.0,
+                Version::SUPPORTED_FORMAT.1
+            ),
+            Error::UnsupportedVersion(None) => write!(f, "no version in the magic number"),
+            Error::InvalidHeader { field, value } => {
+                write!(f, "corrupt header: invalid {} {}", field, value)
+            }
+            Error::UnsupportedDatabaseType(t) => {
+                write!(
+                    f,
+                    "unsupported database type: {} (only hash databases are supported)",
+                    t
+                )
+            }
+            Error::CorruptBucketArray(e) => write!(f, "corrupt bucket array: {}", reason(e)),
+            Error::CorruptFreeBlockPool(e) => {
+                write!(f, "corrupt free block pool: {}", reason(e))
+            }
+            Error::CorruptWal(e) => write!(f, "corrupt write-ahead log: {}", reason(e)),
+            Error::CorruptRecord { offset, source } => {
+                write!(
+                    f,
+                    "corrupt record at offset {:#x}: {}",
+                    offset,
+                    reason(source)
+                )
+            }
+            Error::UnexpectedFreeBlock { offset } => {
+                write!(f, "unexpected free block in chain at offset {:#x}", offset)
+            }
+            Error::ChainCycle { offset } => {
+                write!(f, "cycle in chains at the record at offset {:#x}", offset)
+            }
+            Error::MissingCodec => write!(
+                f,
+                "values are encoded with an external codec (HDBTEXCODEC), but no codec is given"
+            ),
+            Error::Codec { offset, source } => write!(
+                f,
+                "failed to decode the value of the record at offset {:#x}: {}",
+                offset, source
+            ),
+            Error::OffsetOverflow { offset } => write!(
+                f,
+                "offset {:#x} is too large for a database without the large option",
+                offset
+            ),
+            Error::BucketNumberOverflow { bucket_number } => write!(
+                f,
+                "bucket number {} is too large for the offsets of the database",
+                bucket_number
+            ),
+            Error::InvalidEncoding(reason) => write!(f, "invalid encoding: {}", reason),
+            Error::InvalidImport { line, reason } => {
+                write!(f, "invalid input at line {}: {}", line, reason)
+            }
+            Error::Unclean(warnings) => {
+                let warnings: Vec<_> = warnings.iter().map(Warning::to_string).collect();
+                write!(f, "unclean database: {}", warnings.join(", "))
+            }
+            Error::Locked => write!(f, "the database is locked by a writer"),
+            Error::NestedTransaction => write!(f, "a transaction is already in progress"),
+            Error::NoTransaction => write!(f, "no transaction is in progress"),
+            Error::UnknownPath => write!(
+                f,
+                "the path of the database is unknown, which is needed to log a transaction"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::NotClosed => write!(
+                f,
+                "the database is being written or wasn't closed cleanly (HDBFOPEN)"
+            ),
+            Warning::FatalError => write!(f, "a writer hit a fatal error (HDBFFATAL)"),
+            Warning::PendingWal => write!(
+                f,
+                "a transaction was interrupted and the database may be torn until the write-ahead log is applied"
+            ),
+        }
+    }
+}
+
+/// Summarize a binrw error in one line, without its backtrace
+fn reason(e: &binrw::Error) -> String {
+    match e.root_cause() {
+        _ if e.is_eof() => "unexpected end of file".to_string(),
+        binrw::Error::Io(e) => e.to_string(),
+        binrw::Error::BadMagic { found, .. } => format!("bad magic byte {:?}", found),
+        binrw::Error::AssertFail { message, .. } => message.clone(),
+        binrw::Error::Custom { err, .. } => err.to_string(),
+        binrw::Error::EnumErrors { variant_errors, .. } => {
+            // report the variant whose magic byte matched, if any
+            match variant_errors
+                .iter()
+                .find(|(_, e)| !matches!(e.root_cause(), binrw::Error::BadMagic { .. }))
+                .or_else(|| variant_errors.first())
+            {
+                Some((_, e)) => reason(e),
+                None => e.to_string(),
+            }
+        }
+        e => e.to_string(),
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) | Error::Codec { source: e, .. } => Some(e),
+            Error::CorruptHeader(e)
+            | Error::CorruptBucketArray(e)
+            | Error::CorruptFreeBlockPool(e)
+            | Error::CorruptWal(e)
+            | Error::CorruptRecord { source: e, .. } => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    #[inline]
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
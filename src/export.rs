@@ -0,0 +1,238 @@
+//! Export records to formats which other tools can read
+
+use std::{
+    borrow::Cow,
+    io::{self, Read, Seek, Write},
+    str,
+};
+
+use crate::{
+    binrw_types::{Record, RecordSpace, U32orU64},
+    encoding, Result, TCHDB,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// A JSON object per line
+    #[default]
+    JsonLines,
+    /// Comma separated values with a header line, quoted as RFC 4180
+    Csv,
+    /// A MessagePack map per record, concatenated
+    MessagePack,
+}
+
+/// How keys and values are represented as text
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DataEncoding {
+    /// UTF-8 strings, or base64 if they are not valid UTF-8.
+    /// JSON Lines and CSV have the `key_encoding` or `value_encoding` field, which is
+    /// `utf8` or `base64`, and MessagePack has binaries instead of strings.
+    #[default]
+    Utf8,
+    Base64,
+    Hex,
+}
+
+impl DataEncoding {
+    /// The text and the name of the encoding actually used
+    fn encode(self, data: &[u8]) -> (Cow<'_, str>, &'static str) {
+        match self {
+            DataEncoding::Utf8 => match str::from_utf8(data) {
+                Ok(s) => (Cow::Borrowed(s), "utf8"),
+                Err(_) => (Cow::Owned(encoding::encode_base64(data)), "base64"),
+            },
+            DataEncoding::Base64 => (Cow::Owned(encoding::encode_base64(data)), "base64"),
+            DataEncoding::Hex => (Cow::Owned(encoding::encode_hex(data)), "hex"),
+        }
+    }
+}
+
+/// Write records one by one, which doesn't hold records in memory
+#[derive(Clone, Debug, Default)]
+pub struct Exporter {
+    format: Format,
+    key_encoding: DataEncoding,
+    value_encoding: DataEncoding,
+    offset: bool,
+    hash: bool,
+}
+
+impl Exporter {
+    pub fn new(format: Format) -> Self {
+        Exporter {
+            format,
+            ..Self::default()
+        }
+    }
+
+    pub fn key_encoding(mut self, key_encoding: DataEncoding) -> Self {
+        self.key_encoding = key_encoding;
+        self
+    }
+
+    pub fn value_encoding(mut self, value_encoding: DataEncoding) -> Self {
+        self.value_encoding = value_encoding;
+        self
+    }
+
+    /// Add the offset of each record
+    pub fn offset(mut self, offset: bool) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Add the hash value stored in each record
+    pub fn hash(mut self, hash: bool) -> Self {
+        self.hash = hash;
+        self
+    }
+
+    /// Write all records of the database, returns the number of records
+    pub fn export<U, R, W>(&self, tchdb: &mut TCHDB<U, R>, mut writer: W) -> Result<u64>
+    where
+        U: U32orU64,
+        R: Read + Seek,
+        W: Write,
+    {
+        self.write_header(&mut writer)?;
+
+        let mut record_num = 0;
+        for record in tchdb.read_record_spaces(true) {
+            if let RecordSpace::Record(record) = record? {
+                self.write_record(&mut writer, record)?;
+                record_num += 1;
+            }
+        }
+        writer.flush()?;
+
+        Ok(record_num)
+    }
+
+    /// Write the header line of CSV, nothing is written for other formats
+    pub fn write_header<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        if self.format != Format::Csv {
+            return Ok(());
+        }
+
+        let names: Vec<_> = self.fields().into_iter().map(|(name, _)| name).collect();
+        writeln!(writer, "{}", names.join(","))
+    }
+
+    /// Write a record whose value has been read
+    pub fn write_record<U: U32orU64, W: Write>(
+        &self,
+        writer: &mut W,
+        record: Record<U>,
+    ) -> io::Result<()> {
+        let offset = record.space_offset();
+        let hash = record.hash_value;
+        let value = record.value.into_value().into_value();
+        let key = self.key_encoding.encode(&record.key);
+        let value_text = self.value_encoding.encode(&value);
+        let key_fallback = self.key_encoding == DataEncoding::Utf8 && key.1 != "utf8";
+        let value_fallback = self.value_encoding == DataEncoding::Utf8 && value_text.1 != "utf8";
+
+        let fields = self.fields().into_iter().map(|(name, field)| {
+            let value = match field {
+                Field::Key => Value::Data(&key.0, &record.key, key_fallback),
+                Field::KeyEncoding => Value::Str(key.1),
+                Field::Value => Value::Data(&value_text.0, &value, value_fallback),
+                Field::ValueEncoding => Value::Str(value_text.1),
+                Field::Offset => Value::Number(offset),
+                Field::Hash => Value::Number(hash as u64),
+            };
+            (name, value)
+        });
+
+        match self.format {
+            Format::JsonLines => {
+                let mut line = vec![b'{'];
+                for (i, (name, value)) in fields.enumerate() {
+                    if i > 0 {
+                        line.push(b',');
+                    }
+                    serde_json::to_writer(&mut line, name)?;
+                    line.push(b':');
+                    match value {
+                        Value::Data(s, _, _) | Value::Str(s) => {
+                            serde_json::to_writer(&mut line, s)?
+                        }
+                        Value::Number(n) => serde_json::to_writer(&mut line, &n)?,
+                    }
+                }
+                line.extend_from_slice(b"}\n");
+                writer.write_all(&line)
+            }
+            Format::Csv => {
+                let cells: Vec<_> = fields
+                    .map(|(_, value)| match value {
+                        Value::Data(s, _, _) | Value::Str(s) => csv_cell(s),
+                        Value::Number(n) => Cow::Owned(n.to_string()),
+                    })
+                    .collect();
+                writeln!(writer, "{}", cells.join(","))
+            }
+            Format::MessagePack => {
+                let fields: Vec<_> = fields
+                    // the encoding is told by the type
+                    .filter(|(name, _)| !name.ends_with("_encoding"))
+                    .collect();
+                rmp::encode::write_map_len(writer, fields.len() as u32)?;
+                for (name, value) in fields {
+                    rmp::encode::write_str(writer, name)?;
+                    match value {
+                        Value::Data(_, data, true) => rmp::encode::write_bin(writer, data)?,
+                        Value::Data(s, _, _) | Value::Str(s) => rmp::encode::write_str(writer, s)?,
+                        Value::Number(n) => {
+                            rmp::encode::write_uint(writer, n)?;
+                        }
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn fields(&self) -> Vec<(&'static str, Field)> {
+        let mut fields = vec![("key", Field::Key)];
+        if self.key_encoding == DataEncoding::Utf8 {
+            fields.push(("key_encoding", Field::KeyEncoding));
+        }
+        fields.push(("value", Field::Value));
+        if self.value_encoding == DataEncoding::Utf8 {
+            fields.push(("value_encoding", Field::ValueEncoding));
+        }
+        if self.offset {
+            fields.push(("offset", Field::Offset));
+        }
+        if self.hash {
+            fields.push(("hash", Field::Hash));
+        }
+        fields
+    }
+}
+
+enum Field {
+    Key,
+    KeyEncoding,
+    Value,
+    ValueEncoding,
+    Offset,
+    Hash,
+}
+
+enum Value<'a> {
+    /// The text of the data, the data itself, and whether it isn't valid UTF-8
+    Data(&'a str, &'a [u8], bool),
+    Str(&'a str),
+    Number(u64),
+}
+
+fn csv_cell(s: &str) -> Cow<'_, str> {
+    if s.contains([',', '"', '\r', '\n']) {
+        Cow::Owned(format!("\"{}\"", s.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(s)
+    }
+}
@@ -0,0 +1,147 @@
+//! Import records from text formats, the inverse of `export`
+
+use std::{
+    io::{BufRead, Read, Seek, Write},
+    result, str,
+};
+
+use serde_json::Value;
+
+use crate::{binrw_types::U32orU64, encoding, export::DataEncoding, Error, Result, TCHDB};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// A key and a value separated by a tab per line, as `tchmgr importtsv` reads.
+    /// Lines without tabs are ignored.
+    #[default]
+    Tsv,
+    /// A JSON object per line, which has `key` and `value`, as `export` writes.
+    /// The fields `key_encoding` and `value_encoding` take precedence over the encodings
+    /// of the importer.
+    JsonLines,
+}
+
+/// Store records read line by line, which doesn't hold records in memory
+#[derive(Clone, Debug, Default)]
+pub struct Importer {
+    format: Format,
+    key_encoding: DataEncoding,
+    value_encoding: DataEncoding,
+}
+
+impl Importer {
+    pub fn new(format: Format) -> Self {
+        Importer {
+            format,
+            ..Self::default()
+        }
+    }
+
+    /// `DataEncoding::Utf8` takes keys as they are
+    pub fn key_encoding(mut self, key_encoding: DataEncoding) -> Self {
+        self.key_encoding = key_encoding;
+        self
+    }
+
+    /// `DataEncoding::Utf8` takes values as they are
+    pub fn value_encoding(mut self, value_encoding: DataEncoding) -> Self {
+        self.value_encoding = value_encoding;
+        self
+    }
+
+    /// Store all records read from `reader`, returns the number of records
+    pub fn import<U, R, B>(&self, tchdb: &mut TCHDB<U, R>, reader: B) -> Result<u64>
+    where
+        U: U32orU64,
+        R: Read + Write + Seek,
+        B: BufRead,
+    {
+        let mut record_num = 0;
+        for (i, line) in reader.split(b'\n').enumerate() {
+            if let Some((key, value)) = self.parse_line(&line?, i as u64 + 1)? {
+                tchdb.put(key, value)?;
+                record_num += 1;
+            }
+        }
+
+        Ok(record_num)
+    }
+
+    /// Count records without decoding them, to choose the size of a new database
+    pub fn count<B: BufRead>(&self, reader: B) -> Result<u64> {
+        let mut record_num = 0;
+        for line in reader.split(b'\n') {
+            let line = line?;
+            let is_record = match self.format {
+                Format::Tsv => line.contains(&b'\t'),
+                Format::JsonLines => !line.trim_ascii().is_empty(),
+            };
+            if is_record {
+                record_num += 1;
+            }
+        }
+
+        Ok(record_num)
+    }
+
+    /// Parse a line into a key and a value, returns `None` if it has no record
+    fn parse_line(&self, line: &[u8], line_number: u64) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let invalid = |reason: String| Error::InvalidImport {
+            line: line_number,
+            reason,
+        };
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        match self.format {
+            Format::Tsv => {
+                let Some(pos) = line.iter().position(|&b| b == b'\t') else {
+                    return Ok(None);
+                };
+                let key = decode(self.key_encoding, &line[..pos]).map_err(invalid)?;
+                let value = decode(self.value_encoding, &line[pos + 1..]).map_err(invalid)?;
+                Ok(Some((key, value)))
+            }
+            Format::JsonLines => {
+                if line.trim_ascii().is_empty() {
+                    return Ok(None);
+                }
+                let object: Value =
+                    serde_json::from_slice(line).map_err(|e| invalid(e.to_string()))?;
+                let key = json_field(&object, "key", self.key_encoding).map_err(invalid)?;
+                let value = json_field(&object, "value", self.value_encoding).map_err(invalid)?;
+                Ok(Some((key, value)))
+            }
+        }
+    }
+}
+
+fn decode(encoding: DataEncoding, data: &[u8]) -> result::Result<Vec<u8>, String> {
+    let text = || str::from_utf8(data).map_err(|e| e.to_string());
+    let decoded = match encoding {
+        DataEncoding::Utf8 => return Ok(data.to_vec()),
+        DataEncoding::Base64 => encoding::decode_base64(text()?),
+        DataEncoding::Hex => encoding::decode_hex(text()?),
+    };
+    decoded.map_err(|e| e.to_string())
+}
+
+/// Decode the field `name`, whose encoding is in the field `<name>_encoding` if any
+fn json_field(
+    object: &Value,
+    name: &str,
+    encoding: DataEncoding,
+) -> result::Result<Vec<u8>, String> {
+    let text = object
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("no string field {:?}", name))?;
+    let encoding = match object.get(format!("{}_encoding", name)) {
+        None => encoding,
+        Some(Value::String(s)) if s == "utf8" => DataEncoding::Utf8,
+        Some(Value::String(s)) if s == "base64" => DataEncoding::Base64,
+        Some(Value::String(s)) if s == "hex" => DataEncoding::Hex,
+        Some(e) => return Err(format!("unknown encoding {} of {:?}", e, name)),
+    };
+
+    decode(encoding, text.as_bytes())
+}
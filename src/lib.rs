@@ -1,18 +1,50 @@
+#[cfg(feature = "async")]
+mod async_read;
 pub mod binrw_types;
+mod builder;
+pub mod compression;
+pub mod encoding;
+mod error;
+pub mod export;
+pub mod import;
 pub mod load;
+mod lock;
+mod mmap;
 mod multi_read;
+mod optimize;
+mod positional;
+mod salvage;
+mod scan;
+mod transaction;
+mod tree;
+mod verify;
+pub mod wal;
+mod write;
 
 use std::{
     cmp::Ordering,
     io::{Read, Seek, SeekFrom},
     marker::PhantomData,
     mem,
+    path::PathBuf,
 };
 
 use binrw::{BinReaderExt, Endian};
 use binrw_types::U32orU64;
+use compression::{Compression, ValueCodec};
 
-use self::binrw_types::{Buckets, FreeBlockPoolElement, Header, Record, RecordOffset, RecordSpace};
+use self::binrw_types::{Buckets, FreeBlockPool, Header, Record, RecordOffset, RecordSpace};
+use self::transaction::Transaction;
+
+#[cfg(feature = "async")]
+pub use self::async_read::AsyncReader;
+pub use self::builder::TCHDBBuilder;
+pub use self::error::{Error, Result, Warning};
+pub use self::lock::LockMode;
+pub use self::mmap::TCHDBMmap;
+pub use self::positional::{PositionalReader, TCHDBConcurrent};
+pub use self::salvage::{SalvageIter, Salvaged};
+pub use self::verify::Finding;
 
 #[derive(Debug)]
 pub struct KeyWithHash<'a> {
@@ -27,10 +59,21 @@ pub struct TCHDB<U, R> {
     pub header: Header,
     pub bucket_offset: u64, // always be 256
     pub free_block_pool_offset: u64,
+    codec: Option<Box<dyn ValueCodec>>,
+    tran: Option<Transaction>,
+    // the log of an interrupted transaction is left, found on opening the file
+    pending_wal: bool,
+    // the path of the file opened to modify, next to which transactions are logged
+    path: Option<PathBuf>,
     bucket_type: PhantomData<fn() -> U>,
 }
 
 impl<U, R> TCHDB<U, R> {
+    /// Register the codec to decode values of a database with `HDBTEXCODEC`
+    pub fn set_codec(&mut self, codec: Box<dyn ValueCodec>) {
+        self.codec = Some(codec);
+    }
+
     pub fn hash<'a>(&self, key: &'a [u8]) -> KeyWithHash<'a> {
         let mut idx: u64 = 19780211;
         for &b in key {
@@ -38,32 +81,42 @@ impl<U, R> TCHDB<U, R> {
         }
         idx %= self.header.bucket_number;
 
-        let mut hash: u32 = 751;
-        for &b in key.into_iter().rev() {
-            hash = hash.wrapping_mul(31) ^ b as u32;
-        }
-
         KeyWithHash {
             key,
             idx,
-            hash: hash as u8,
+            hash: hash_value(key),
         }
     }
 }
 
+/// The hash value stored in records, which orders records in a bucket
+fn hash_value(key: &[u8]) -> u8 {
+    let mut hash: u32 = 751;
+    for &b in key.iter().rev() {
+        hash = hash.wrapping_mul(31) ^ b as u32;
+    }
+    hash as u8
+}
+
 impl<U, R: Seek> TCHDB<U, R> {
     pub fn read_record_spaces<'a>(&'a mut self, pv: bool) -> RecordSpaceIter<'a, U, R> {
-        RecordSpaceIter::new(&mut self.reader, pv, self.endian, &self.header)
+        RecordSpaceIter::new(
+            &mut self.reader,
+            pv,
+            self.endian,
+            &self.header,
+            self.codec.as_deref(),
+        )
     }
 }
 
-impl<U, R: Read + Seek> TCHDB<U, R> {
-    fn new(mut reader: R, endian: Endian, header: Header) -> Self {
-        let bucket_offset = reader.stream_position().unwrap();
-        debug_assert_eq!(bucket_offset, 256);
-
-        let free_block_pool_offset =
-            bucket_offset + header.bucket_number * mem::size_of::<U>() as u64;
+impl<U, R> TCHDB<U, R> {
+    fn with_header(reader: R, endian: Endian, header: Header, bucket_offset: u64) -> Self {
+        // saturate not to panic on a header read with the wrong endian
+        let free_block_pool_offset = header
+            .bucket_number
+            .saturating_mul(mem::size_of::<U>() as u64)
+            .saturating_add(bucket_offset);
 
         TCHDB {
             reader,
@@ -71,203 +124,241 @@ impl<U, R: Read + Seek> TCHDB<U, R> {
             header,
             bucket_offset,
             free_block_pool_offset,
+            codec: None,
+            tran: None,
+            pending_wal: false,
+            path: None,
             bucket_type: PhantomData,
         }
     }
+}
+
+impl<U, R: Read + Seek> TCHDB<U, R> {
+    fn new(mut reader: R, endian: Endian, header: Header) -> Result<Self> {
+        let bucket_offset = reader.stream_position()?;
+        debug_assert_eq!(bucket_offset, 256);
+
+        Ok(Self::with_header(reader, endian, header, bucket_offset))
+    }
 
-    pub fn read_free_block_pool(&mut self) -> Vec<FreeBlockPoolElement> {
+    pub fn read_free_block_pool(&mut self) -> Result<FreeBlockPool> {
         self.reader
-            .seek(SeekFrom::Start(self.free_block_pool_offset))
-            .unwrap();
-
-        let pool_size = 2usize.pow(self.header.free_block_pool_power as u32);
-        let mut pool = Vec::with_capacity(pool_size);
-        loop {
-            let elem: FreeBlockPoolElement = self.reader.read_type(self.endian).unwrap();
-            if elem.offset.0 == 0 && elem.size.0 == 0 {
-                break;
-            }
-            pool.push(elem);
-        }
-        pool
+            .seek(SeekFrom::Start(self.free_block_pool_offset))?;
+
+        self.reader
+            .read_type_args(
+                self.endian,
+                (self.header.alignment_power, self.free_block_pool_capacity()),
+            )
+            .map_err(Error::CorruptFreeBlockPool)
+    }
+
+    /// The maximum number of elements of the free block pool
+    #[inline]
+    pub fn free_block_pool_capacity(&self) -> usize {
+        1 << self.header.free_block_pool_power
     }
 }
 
 impl<U: U32orU64, R: Read + Seek> TCHDB<U, R> {
-    pub fn read_buckets(&mut self) -> Buckets<U> {
-        self.reader
-            .seek(SeekFrom::Start(self.bucket_offset))
-            .unwrap();
+    pub fn read_buckets(&mut self) -> Result<Buckets<U>> {
+        self.reader.seek(SeekFrom::Start(self.bucket_offset))?;
         let buckets = self
             .reader
             .read_type_args(self.endian, (self.header.bucket_number,))
-            .unwrap();
+            .map_err(Error::CorruptBucketArray)?;
 
-        debug_assert_eq!(
-            self.reader.stream_position().unwrap(),
-            self.free_block_pool_offset
-        );
+        debug_assert_eq!(self.reader.stream_position()?, self.free_block_pool_offset);
 
-        buckets
+        Ok(buckets)
     }
 
-    fn read_bucket(&mut self, idx: u64) -> RecordOffset<U> {
+    fn read_bucket(&mut self, idx: u64) -> Result<RecordOffset<U>> {
         let pos = self.bucket_offset + mem::size_of::<U>() as u64 * idx;
-        self.reader.seek(SeekFrom::Start(pos)).unwrap();
-        self.reader.read_type(self.endian).unwrap()
+        self.reader.seek(SeekFrom::Start(pos))?;
+        self.reader
+            .read_type(self.endian)
+            .map_err(Error::CorruptBucketArray)
+    }
+
+    fn read_record_space(
+        &mut self,
+        rec_off: RecordOffset<U>,
+        read_value: bool,
+    ) -> Result<RecordSpace<U>> {
+        self.read_record_space_at(rec_off.offset(self.header.alignment_power), read_value)
     }
 
-    fn read_record_space(&mut self, rec_off: RecordOffset<U>, read_value: bool) -> RecordSpace<U> {
-        let offset = rec_off.offset(self.header.alignment_power);
-        self.reader.seek(SeekFrom::Start(offset)).unwrap();
+    pub(crate) fn read_record_space_at(
+        &mut self,
+        offset: u64,
+        read_value: bool,
+    ) -> Result<RecordSpace<U>> {
+        self.reader.seek(SeekFrom::Start(offset))?;
         self.reader
-            .read_type_args(self.endian, (offset, read_value))
-            .unwrap()
+            .read_type_args(
+                self.endian,
+                (offset, read_value, self.header.options.compression()),
+            )
+            .map_err(Error::corrupt_record(offset))
     }
 
-    pub fn get_record(&mut self, key: &KeyWithHash) -> Option<Record<U>> {
-        let (found, mut log) = self.get_record_detail(key);
+    pub fn get_record(&mut self, key: &KeyWithHash) -> Result<Option<Record<U>>> {
+        let (found, mut log) = self.get_record_detail(key)?;
         if found {
-            Some(log.remove(log.len() - 1))
+            Ok(Some(log.remove(log.len() - 1)))
         } else {
-            None
+            Ok(None)
         }
     }
 
-    pub fn get_record_detail(&mut self, key: &KeyWithHash) -> (bool, Vec<Record<U>>) {
-        let mut rec_off = self.read_bucket(key.idx);
-
-        let mut visited_records = Vec::new();
-        loop {
-            if rec_off.is_empty() {
-                return (false, visited_records);
-            }
+    pub fn get_record_detail(&mut self, key: &KeyWithHash) -> Result<(bool, Vec<Record<U>>)> {
+        let bucket = self.read_bucket(key.idx)?;
+        tree::search(key, bucket, self.header.alignment_power, |offset| {
+            tree::expect_record(offset, self.read_record_space_at(offset, false)?)
+        })
+    }
 
-            let record = match self.read_record_space(rec_off, false) {
-                RecordSpace::FreeBlock(_) => return (false, visited_records),
-                RecordSpace::Record(r) => r,
-            };
-
-            if key.hash > record.hash_value {
-                rec_off = record.left_chain;
-                visited_records.push(record);
-                continue;
-            } else if key.hash < record.hash_value {
-                rec_off = record.right_chain;
-                visited_records.push(record);
-                continue;
-            }
+    pub fn read_value(&mut self, record: &mut Record<U>) -> Result<()> {
+        if record.value.is_read() {
+            return Ok(());
+        }
 
-            match key.key.cmp(&record.key) {
-                Ordering::Greater => {
-                    rec_off = record.left_chain;
-                    visited_records.push(record);
-                    continue;
-                }
-                Ordering::Less => {
-                    rec_off = record.right_chain;
-                    visited_records.push(record);
-                    continue;
-                }
-                Ordering::Equal => {
-                    visited_records.push(record);
-                    return (true, visited_records);
-                }
-            }
+        record
+            .value
+            .read_value(&mut self.reader)
+            .map_err(Error::corrupt_record(record.space_offset()))?;
+        if self.header.options.compression() == Compression::External {
+            decode_value(self.codec.as_deref(), record)?;
         }
+
+        Ok(())
     }
 
-    pub fn get(&mut self, key_str: &str) -> Option<Vec<u8>> {
-        let key = self.hash(key_str.as_bytes());
-        match self.get_record(&key) {
-            None => None,
+    pub fn get<K: AsRef<[u8]>>(&mut self, key: K) -> Result<Option<Vec<u8>>> {
+        let key = self.hash(key.as_ref());
+        match self.get_record(&key)? {
+            None => Ok(None),
             Some(mut record) => {
-                record.value.read_value(&mut self.reader);
+                self.read_value(&mut record)?;
                 let value = record.value.into_value();
-                Some(value.into_value())
+                Ok(Some(value.into_value()))
             }
         }
     }
 
-    pub fn get_detail<'a>(&mut self, key_str: &'a str) -> (KeyWithHash<'a>, bool, Vec<Record<U>>) {
-        let key = self.hash(key_str.as_bytes());
-        let (found, visited_records) = self.get_record_detail(&key);
-        (key, found, visited_records)
+    pub fn get_detail<'a, K: AsRef<[u8]> + ?Sized>(
+        &mut self,
+        key: &'a K,
+    ) -> Result<(KeyWithHash<'a>, bool, Vec<Record<U>>)> {
+        let key = self.hash(key.as_ref());
+        let (found, visited_records) = self.get_record_detail(&key)?;
+        Ok((key, found, visited_records))
     }
 
-    pub fn dump_bucket(&mut self, bucket_number: u64) -> Vec<Record<U>> {
-        let mut records = Vec::new();
-        let rec_off = self.read_bucket(bucket_number);
-
-        self.traverse_records(rec_off, &mut records);
-
-        records
+    pub fn dump_bucket(&mut self, bucket_number: u64) -> Result<Vec<Record<U>>> {
+        let rec_off = self.read_bucket(bucket_number)?;
+        tree::traverse(rec_off, self.header.alignment_power, |offset| {
+            tree::expect_record(offset, self.read_record_space_at(offset, false)?)
+        })
     }
+}
 
-    fn traverse_records(&mut self, rec_off: RecordOffset<U>, records: &mut Vec<Record<U>>) {
-        if rec_off.is_empty() {
-            return;
-        }
+/// Compare a key with a record in the order of the binary trees in buckets,
+/// greater keys are linked by the left chain
+fn compare_record<U: U32orU64>(key: &KeyWithHash, record: &Record<U>) -> Ordering {
+    key.hash
+        .cmp(&record.hash_value)
+        .then_with(|| compare_keys(key.key, &record.key))
+}
 
-        match self.read_record_space(rec_off, false) {
-            RecordSpace::FreeBlock(_) => panic!(
-                "unexpected freespace found: {}",
-                rec_off.offset(self.header.alignment_power)
-            ),
-            RecordSpace::Record(record) => {
-                let right = record.right_chain;
-                let left = record.left_chain;
-                self.traverse_records(right, records);
-                records.push(record);
-                self.traverse_records(left, records);
-            }
-        }
-    }
+/// Keys are compared by their lengths first, as `tcreckeycmp` does
+fn compare_keys(a: &[u8], b: &[u8]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+fn decode_value<U: U32orU64>(codec: Option<&dyn ValueCodec>, record: &mut Record<U>) -> Result<()> {
+    let codec = codec.ok_or(Error::MissingCodec)?;
+    record.decode_value(codec).map_err(|source| Error::Codec {
+        offset: record.space_offset(),
+        source,
+    })
 }
 
 pub struct RecordSpaceIter<'a, U, R> {
     reader: &'a mut R,
+    codec: Option<&'a dyn ValueCodec>,
     pv: bool,
     endian: Endian,
+    compression: Compression,
     file_size: u64,
     next_pos: u64,
     bucket_type: PhantomData<fn() -> U>,
 }
 
 impl<'a, U, R: Seek> RecordSpaceIter<'a, U, R> {
-    fn new(reader: &'a mut R, pv: bool, endian: Endian, header: &Header) -> Self {
+    pub(crate) fn new(
+        reader: &'a mut R,
+        pv: bool,
+        endian: Endian,
+        header: &Header,
+        codec: Option<&'a dyn ValueCodec>,
+    ) -> Self {
         RecordSpaceIter {
             reader,
+            codec,
             pv,
             endian,
+            compression: header.options.compression(),
             file_size: header.file_size,
             next_pos: header.first_record,
             bucket_type: PhantomData,
         }
     }
+
+    /// Iterate only over record spaces from `start` to `end`, which must be on the boundaries
+    pub(crate) fn range(mut self, start: u64, end: u64) -> Self {
+        self.next_pos = start;
+        self.file_size = end;
+        self
+    }
 }
 
 impl<'a, U: U32orU64, R: Read + Seek> Iterator for RecordSpaceIter<'a, U, R> {
-    type Item = RecordSpace<U>;
+    type Item = Result<RecordSpace<U>>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.next_pos >= self.file_size {
             return None;
         }
 
-        self.reader.seek(SeekFrom::Start(self.next_pos)).unwrap();
+        let result = self.read_next();
+        if result.is_err() {
+            // stop iterating, the position of the next record is unknown
+            self.next_pos = self.file_size;
+        }
+        Some(result)
+    }
+}
+
+impl<'a, U: U32orU64, R: Read + Seek> RecordSpaceIter<'a, U, R> {
+    fn read_next(&mut self) -> Result<RecordSpace<U>> {
+        self.reader.seek(SeekFrom::Start(self.next_pos))?;
         match self
             .reader
-            .read_type_args(self.endian, (self.next_pos, self.pv))
-            .unwrap()
+            .read_type_args(self.endian, (self.next_pos, self.pv, self.compression))
+            .map_err(Error::corrupt_record(self.next_pos))?
         {
             RecordSpace::FreeBlock(free_block) => {
-                self.next_pos = self.reader.stream_position().unwrap();
-                Some(RecordSpace::FreeBlock(free_block))
+                self.next_pos = self.reader.stream_position()?;
+                Ok(RecordSpace::FreeBlock(free_block))
             }
-            RecordSpace::Record(record) => {
+            RecordSpace::Record(mut record) => {
                 self.next_pos = record.next_record();
-                Some(RecordSpace::Record(record))
+                if self.pv && self.compression == Compression::External {
+                    decode_value(self.codec, &mut record)?;
+                }
+                Ok(RecordSpace::Record(record))
             }
         }
     }
@@ -1,41 +1,454 @@
 use std::{
-    fs::File,
-    io::{Read, Seek, SeekFrom},
+    fs::{File, OpenOptions},
+    io::{Cursor, Read, Seek, SeekFrom},
     path::Path,
 };
 
 use binrw::{io::BufReader, BinReaderExt, Endian};
+use memmap2::Mmap;
+#[cfg(feature = "async")]
+use tokio::io::{AsyncRead, AsyncSeek};
 
-use crate::{binrw_types::Header, TCHDB};
+#[cfg(feature = "async")]
+use crate::AsyncReader;
+
+use crate::{
+    binrw_types::Header,
+    builder::{MAX_ALIGNMENT_POWER, MAX_FREE_BLOCK_POOL_POWER},
+    compression::ValueCodec,
+    lock,
+    wal::{self, WalOverlay},
+    Error, LockMode, PositionalReader, Result, Warning, TCHDB,
+};
+
+/// The bucket array follows the header
+#[cfg(feature = "async")]
+const HEADER_SIZE: u64 = 256;
 
 pub enum TCHDBLoaded<R> {
     Small(TCHDB<u32, R>),
     Large(TCHDB<u64, R>),
 }
 
-pub fn open_with_endian<T>(path: T, endian: Endian) -> TCHDBLoaded<BufReader<File>>
+impl<R> TCHDBLoaded<R> {
+    pub fn header(&self) -> &Header {
+        match self {
+            TCHDBLoaded::Small(tchdb) => &tchdb.header,
+            TCHDBLoaded::Large(tchdb) => &tchdb.header,
+        }
+    }
+
+    pub fn endian(&self) -> Endian {
+        match self {
+            TCHDBLoaded::Small(tchdb) => tchdb.endian,
+            TCHDBLoaded::Large(tchdb) => tchdb.endian,
+        }
+    }
+
+    /// Warnings told by the additional flags of the header, and the write-ahead log left
+    /// beside the file if the database is opened from a path
+    pub fn warnings(&self) -> Vec<Warning> {
+        let flags = self.header().additional_flags;
+        let mut warnings = Vec::new();
+        if flags.open {
+            warnings.push(Warning::NotClosed);
+        }
+        if flags.fatal {
+            warnings.push(Warning::FatalError);
+        }
+        let pending_wal = match self {
+            TCHDBLoaded::Small(tchdb) => tchdb.pending_wal,
+            TCHDBLoaded::Large(tchdb) => tchdb.pending_wal,
+        };
+        if pending_wal {
+            warnings.push(Warning::PendingWal);
+        }
+        warnings
+    }
+
+    fn pending_wal(mut self, pending: bool) -> Self {
+        match &mut self {
+            TCHDBLoaded::Small(tchdb) => tchdb.pending_wal = pending,
+            TCHDBLoaded::Large(tchdb) => tchdb.pending_wal = pending,
+        }
+        self
+    }
+
+    /// Remember the path of a file opened to modify, to log transactions next to it
+    pub(crate) fn path<T: AsRef<Path>>(mut self, path: T) -> Self {
+        let path = Some(path.as_ref().to_path_buf());
+        match &mut self {
+            TCHDBLoaded::Small(tchdb) => tchdb.path = path,
+            TCHDBLoaded::Large(tchdb) => tchdb.path = path,
+        }
+        self
+    }
+
+    /// The strict mode, which refuses a database with warnings not to read torn records
+    pub fn strict(self) -> Result<Self> {
+        let warnings = self.warnings();
+        if warnings.is_empty() {
+            Ok(self)
+        } else {
+            Err(Error::Unclean(warnings))
+        }
+    }
+
+    /// Register the codec to decode values of a database with `HDBTEXCODEC`
+    pub fn set_codec(&mut self, codec: Box<dyn ValueCodec>) {
+        match self {
+            TCHDBLoaded::Small(tchdb) => tchdb.set_codec(codec),
+            TCHDBLoaded::Large(tchdb) => tchdb.set_codec(codec),
+        }
+    }
+}
+
+impl<R: Into<PositionalReader>> TCHDBLoaded<R> {
+    fn into_concurrent(self) -> TCHDBLoaded<PositionalReader> {
+        match self {
+            TCHDBLoaded::Small(tchdb) => TCHDBLoaded::Small(tchdb.into_concurrent()),
+            TCHDBLoaded::Large(tchdb) => TCHDBLoaded::Large(tchdb.into_concurrent()),
+        }
+    }
+}
+
+pub fn open_with_endian<T>(path: T, endian: Endian) -> Result<TCHDBLoaded<BufReader<File>>>
 where
     T: AsRef<Path>,
 {
-    let file = File::open(path).unwrap();
+    let file = File::open(&path)?;
     let file = BufReader::new(file);
-    load_with_endian(file, endian)
+    Ok(load_with_endian(file, endian)?.pending_wal(wal::is_pending(&path)?))
+}
+
+pub fn open_with_codec<T>(
+    path: T,
+    endian: Endian,
+    codec: Box<dyn ValueCodec>,
+) -> Result<TCHDBLoaded<BufReader<File>>>
+where
+    T: AsRef<Path>,
+{
+    let mut loaded = open_with_endian(path, endian)?;
+    loaded.set_codec(codec);
+    Ok(loaded)
+}
+
+/// Open a database, detecting its endian by `detect_endian`
+pub fn open<T>(path: T) -> Result<TCHDBLoaded<BufReader<File>>>
+where
+    T: AsRef<Path>,
+{
+    let file = File::open(&path)?;
+    Ok(load(BufReader::new(file))?.pending_wal(wal::is_pending(&path)?))
+}
+
+/// Open a database with the shared lock to read it safely while tokyo cabinet writers may
+/// open it. The lock is held until the database is dropped, but also released when another
+/// file descriptor of the same file is closed in the process, as `fcntl` locks are.
+pub fn open_locked_with_endian<T>(
+    path: T,
+    endian: Endian,
+    mode: LockMode,
+) -> Result<TCHDBLoaded<BufReader<File>>>
+where
+    T: AsRef<Path>,
+{
+    let file = File::open(&path)?;
+    lock::lock_shared(&file, mode)?;
+    Ok(load_with_endian(BufReader::new(file), endian)?.pending_wal(wal::is_pending(&path)?))
+}
+
+pub fn open_locked<T>(path: T, mode: LockMode) -> Result<TCHDBLoaded<BufReader<File>>>
+where
+    T: AsRef<Path>,
+{
+    let file = File::open(&path)?;
+    lock::lock_shared(&file, mode)?;
+    Ok(load(BufReader::new(file))?.pending_wal(wal::is_pending(&path)?))
+}
+
+/// Open a database as it was before the transaction interrupted, whose write-ahead log is
+/// left, without modifying the file. The database is read as it is if there is no log.
+pub fn open_with_wal<T>(path: T) -> Result<TCHDBLoaded<WalOverlay<BufReader<File>>>>
+where
+    T: AsRef<Path>,
+{
+    let wal = wal::open(&path)?;
+    let file = File::open(path)?;
+    load(WalOverlay::new(BufReader::new(file), wal)?)
+}
+
+/// Open a database to modify
+pub fn open_writable_with_endian<T>(path: T, endian: Endian) -> Result<TCHDBLoaded<File>>
+where
+    T: AsRef<Path>,
+{
+    let file = OpenOptions::new().read(true).write(true).open(&path)?;
+    Ok(load_with_endian(file, endian)?
+        .pending_wal(wal::is_pending(&path)?)
+        .path(path))
+}
+
+pub fn open_writable<T>(path: T) -> Result<TCHDBLoaded<File>>
+where
+    T: AsRef<Path>,
+{
+    let file = OpenOptions::new().read(true).write(true).open(&path)?;
+    Ok(load(file)?.pending_wal(wal::is_pending(&path)?).path(path))
+}
+
+/// Open a database mapped in memory
+///
+/// # Safety
+///
+/// The file must not be modified while it is mapped, see `memmap2::Mmap::map`.
+pub unsafe fn open_mmap_with_endian<T>(path: T, endian: Endian) -> Result<TCHDBLoaded<Cursor<Mmap>>>
+where
+    T: AsRef<Path>,
+{
+    let file = File::open(&path)?;
+    let mmap = Mmap::map(&file)?;
+    Ok(load_with_endian(Cursor::new(mmap), endian)?.pending_wal(wal::is_pending(&path)?))
+}
+
+/// Open a database mapped in memory
+///
+/// # Safety
+///
+/// The file must not be modified while it is mapped, see `memmap2::Mmap::map`.
+pub unsafe fn open_mmap<T>(path: T) -> Result<TCHDBLoaded<Cursor<Mmap>>>
+where
+    T: AsRef<Path>,
+{
+    let file = File::open(&path)?;
+    let mmap = Mmap::map(&file)?;
+    Ok(load(Cursor::new(mmap))?.pending_wal(wal::is_pending(&path)?))
+}
+
+/// Open a database which can be shared between threads
+pub fn open_concurrent_with_endian<T>(
+    path: T,
+    endian: Endian,
+) -> Result<TCHDBLoaded<PositionalReader>>
+where
+    T: AsRef<Path>,
+{
+    let file = File::open(&path)?;
+    Ok(load_with_endian(file, endian)?
+        .pending_wal(wal::is_pending(&path)?)
+        .into_concurrent())
+}
+
+pub fn open_concurrent<T>(path: T) -> Result<TCHDBLoaded<PositionalReader>>
+where
+    T: AsRef<Path>,
+{
+    let file = File::open(&path)?;
+    Ok(load(file)?
+        .pending_wal(wal::is_pending(&path)?)
+        .into_concurrent())
+}
+
+/// Load a database, detecting its endian by `detect_endian`
+pub fn load<R: Read + Seek>(mut reader: R) -> Result<TCHDBLoaded<R>> {
+    let endian = detect_endian(&mut reader)?;
+    load_with_endian(reader, endian)
+}
+
+/// Detect the endian by the consistency of the header with the file, as some builds of
+/// tokyo cabinet write big endian files. Little endian, which the specification defines,
+/// is chosen if the header is consistent in neither endian.
+pub fn detect_endian<R: Read + Seek>(reader: &mut R) -> Result<Endian> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    for endian in [Endian::Little, Endian::Big] {
+        reader.seek(SeekFrom::Start(0))?;
+        let header: Header = match reader.read_type(endian) {
+            Ok(header) => header,
+            Err(_) => continue,
+        };
+        if !is_consistent(&header, file_len) {
+            continue;
+        }
+        if header.first_record == header.file_size {
+            return Ok(endian);
+        }
+
+        let mut magic = [0];
+        reader.seek(SeekFrom::Start(header.first_record))?;
+        reader.read_exact(&mut magic)?;
+        if is_record_magic(magic[0]) {
+            return Ok(endian);
+        }
+    }
+
+    Ok(Endian::Little)
+}
+
+/// Whether the numbers in the header are consistent with each other and the file length
+fn is_consistent(header: &Header, file_len: u64) -> bool {
+    let bucket_size = if header.options.large { 8 } else { 4 };
+    header.alignment_power < 64
+        && header.bucket_number > 0
+        && header
+            .bucket_number
+            .checked_mul(bucket_size)
+            .and_then(|size| size.checked_add(256))
+            .is_some_and(|end| end <= header.first_record)
+        && header
+            .first_record
+            .is_multiple_of(1 << header.alignment_power)
+        && header.first_record <= header.file_size
+        && header.file_size <= file_len
+}
+
+/// The first byte of a record or a free block
+#[inline]
+fn is_record_magic(b: u8) -> bool {
+    b == 0xc8 || b == 0xb0
+}
+
+pub fn load_with_endian<R: Read + Seek>(mut reader: R, endian: Endian) -> Result<TCHDBLoaded<R>> {
+    reader.seek(SeekFrom::Start(0))?;
+    let header: Header = reader.read_type(endian).map_err(Error::CorruptHeader)?;
+    check_header(&header)?;
+
+    if header.options.large {
+        Ok(TCHDBLoaded::Large(TCHDB::new(reader, endian, header)?))
+    } else {
+        Ok(TCHDBLoaded::Small(TCHDB::new(reader, endian, header)?))
+    }
+}
+
+/// Open a database to read with the async API
+#[cfg(feature = "async")]
+pub async fn open_async_with_endian<T>(
+    path: T,
+    endian: Endian,
+) -> Result<TCHDBLoaded<AsyncReader<tokio::fs::File>>>
+where
+    T: AsRef<Path>,
+{
+    let file = tokio::fs::File::open(&path).await?;
+    Ok(load_async_with_endian(file, endian)
+        .await?
+        .pending_wal(wal::is_pending_async(&path).await?))
 }
 
-pub fn open<T>(path: T) -> TCHDBLoaded<BufReader<File>>
+#[cfg(feature = "async")]
+pub async fn open_async<T>(path: T) -> Result<TCHDBLoaded<AsyncReader<tokio::fs::File>>>
 where
     T: AsRef<Path>,
 {
-    open_with_endian(path, Endian::Little)
+    let file = tokio::fs::File::open(&path).await?;
+    Ok(load_async(file)
+        .await?
+        .pending_wal(wal::is_pending_async(&path).await?))
+}
+
+/// An async version of `load`
+#[cfg(feature = "async")]
+pub async fn load_async<R>(reader: R) -> Result<TCHDBLoaded<AsyncReader<R>>>
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+{
+    let mut reader = AsyncReader::new(reader);
+    let endian = detect_endian_async(&mut reader).await?;
+    load_async_reader(reader, endian).await
 }
 
-pub fn load_with_endian<R: Read + Seek>(mut reader: R, endian: Endian) -> TCHDBLoaded<R> {
-    reader.seek(SeekFrom::Start(0)).unwrap();
-    let header: Header = reader.read_type(endian).unwrap();
+#[cfg(feature = "async")]
+pub async fn load_async_with_endian<R>(
+    reader: R,
+    endian: Endian,
+) -> Result<TCHDBLoaded<AsyncReader<R>>>
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+{
+    load_async_reader(AsyncReader::new(reader), endian).await
+}
+
+#[cfg(feature = "async")]
+async fn load_async_reader<R>(
+    mut reader: AsyncReader<R>,
+    endian: Endian,
+) -> Result<TCHDBLoaded<AsyncReader<R>>>
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+{
+    let header: Header = reader
+        .parse_at(0, endian, ())
+        .await
+        .map_err(Error::CorruptHeader)?;
+    check_header(&header)?;
 
-    if header.options & 0x01 == 0x01 {
-        TCHDBLoaded::Large(TCHDB::new(reader, endian, header))
+    if header.options.large {
+        Ok(TCHDBLoaded::Large(TCHDB::with_header(
+            reader,
+            endian,
+            header,
+            HEADER_SIZE,
+        )))
     } else {
-        TCHDBLoaded::Small(TCHDB::new(reader, endian, header))
+        Ok(TCHDBLoaded::Small(TCHDB::with_header(
+            reader,
+            endian,
+            header,
+            HEADER_SIZE,
+        )))
     }
 }
+
+/// An async version of `detect_endian`
+#[cfg(feature = "async")]
+async fn detect_endian_async<R>(reader: &mut AsyncReader<R>) -> Result<Endian>
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+{
+    let file_len = reader.len().await?;
+    for endian in [Endian::Little, Endian::Big] {
+        let Ok(header) = reader.parse_at::<Header, _>(0, endian, ()).await else {
+            continue;
+        };
+        if !is_consistent(&header, file_len) {
+            continue;
+        }
+        if header.first_record == header.file_size {
+            return Ok(endian);
+        }
+
+        let magic = reader.read_at(header.first_record, 1).await?;
+        if magic.first().is_some_and(|&b| is_record_magic(b)) {
+            return Ok(endian);
+        }
+    }
+
+    Ok(Endian::Little)
+}
+
+fn check_header(header: &Header) -> Result<()> {
+    if !header.magic_number.starts_with(b"ToKyO CaBiNeT") {
+        return Err(Error::BadMagic);
+    }
+    // don't misread a format which may be laid out differently
+    if !header.version.is_some_and(|v| v.is_supported()) {
+        return Err(Error::UnsupportedVersion(header.version));
+    }
+    if header.database_type != 0 {
+        return Err(Error::UnsupportedDatabaseType(header.database_type));
+    }
+
+    // these parameters are used to divide and shift
+    let invalid = |field, value| Err(Error::InvalidHeader { field, value });
+    if header.bucket_number == 0 {
+        return invalid("bucket number", 0);
+    }
+    if header.alignment_power > MAX_ALIGNMENT_POWER {
+        return invalid("alignment power", header.alignment_power as u64);
+    }
+    if header.free_block_pool_power > MAX_FREE_BLOCK_POOL_POWER {
+        return invalid("free block pool power", header.free_block_pool_power as u64);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+//! Advisory locks compatible with tokyo cabinet, which locks the whole file with `fcntl`
+
+use std::fs::File;
+
+use crate::Result;
+
+/// How to take the shared lock, which conflicts with the exclusive lock of writers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LockMode {
+    /// Wait until writers close the database
+    #[default]
+    Blocking,
+    /// Fail with `Error::Locked` while a writer opens the database, as `HDBOLCKNB` does
+    NonBlocking,
+}
+
+/// Take the shared lock, which is released when the file is closed
+#[cfg(unix)]
+pub(crate) fn lock_shared(file: &File, mode: LockMode) -> Result<()> {
+    use std::{io, os::unix::io::AsRawFd};
+
+    use crate::Error;
+
+    // lock the whole file as `tclock` does
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = libc::F_RDLCK as _;
+    lock.l_whence = libc::SEEK_SET as _;
+    lock.l_start = 0;
+    lock.l_len = 0;
+    let cmd = match mode {
+        LockMode::Blocking => libc::F_SETLKW,
+        LockMode::NonBlocking => libc::F_SETLK,
+    };
+
+    loop {
+        if unsafe { libc::fcntl(file.as_raw_fd(), cmd, &lock) } != -1 {
+            return Ok(());
+        }
+        let e = io::Error::last_os_error();
+        match e.raw_os_error() {
+            Some(libc::EINTR) => continue,
+            Some(libc::EAGAIN | libc::EACCES) if mode == LockMode::NonBlocking => {
+                return Err(Error::Locked)
+            }
+            _ => return Err(Error::Io(e)),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn lock_shared(_file: &File, _mode: LockMode) -> Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "locks are only supported on unix",
+    )
+    .into())
+}
@@ -1,23 +1,44 @@
 use std::{
-    io::{self, BufWriter, Read, Seek, Write},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Cursor, Read, Seek, Write},
     path::Path,
+    process,
+    time::UNIX_EPOCH,
 };
 
 use binrw::Endian;
 use structopt::StructOpt;
 
 use tchread::{
-    binrw_types::{Buckets, RecordSpace, U32orU64},
+    binrw_types::{AdditionalFlags, Buckets, Options, Record, RecordSpace, U32orU64},
+    encoding,
+    export::{self, DataEncoding, Exporter},
+    import::{self, Importer},
     load::{self, TCHDBLoaded},
-    TCHDB,
+    wal, Error, LockMode, PositionalReader, Result, Salvaged, TCHDBBuilder, TCHDB,
 };
 
+/// The size of the buffer of each thread to print records
+const PARALLEL_BUFFER_SIZE: usize = 64 * 1024;
+
 #[derive(StructOpt)]
 /// A tool to read TokyoCabinet hash database files
 struct Command {
     #[structopt(long)]
-    /// Read a bigendian file (which violates the specification)
+    /// Read a bigendian file (which violates the specification), instead of detecting the endian
     bigendian: bool,
+    #[structopt(long, conflicts_with = "bigendian")]
+    /// Read a littleendian file, instead of detecting the endian
+    littleendian: bool,
+    #[structopt(long)]
+    /// Refuse a database which wasn't closed cleanly or hit a fatal error, instead of warning
+    strict: bool,
+    #[structopt(long)]
+    /// Wait for tokyo cabinet writers to close the database and lock it while reading
+    lock: bool,
+    #[structopt(long, conflicts_with = "lock")]
+    /// Fail if a tokyo cabinet writer opens the database, or lock it while reading
+    lock_nb: bool,
     #[structopt(subcommand)]
     sub_command: SubCommand,
 }
@@ -30,29 +51,112 @@ enum SubCommand {
     TraceToGet(TraceToGet),
     DumpBucket(DumpBucket),
     List(List),
+    Header(Header),
+    Inform(Inform),
     Inspect(Inspect),
+    Export(Export),
+    Check(Check),
+    Salvage(Salvage),
+    RecoverWal(RecoverWal),
+    Optimize(Optimize),
+    Create(Create),
+    Import(Import),
 }
 
 fn main() {
     let command = Command::from_args();
     let endian = if command.bigendian {
-        Endian::Big
+        Some(Endian::Big)
+    } else if command.littleendian {
+        Some(Endian::Little)
+    } else {
+        None
+    };
+    let lock = if command.lock {
+        Some(LockMode::Blocking)
+    } else if command.lock_nb {
+        Some(LockMode::NonBlocking)
     } else {
-        Endian::Little
+        None
     };
-    match command.sub_command {
-        SubCommand::Test(test) => run_with_endian(test, endian),
-        SubCommand::Get(get) => run_with_endian(get, endian),
-        SubCommand::TraceToGet(trace_to_get) => run_with_endian(trace_to_get, endian),
-        SubCommand::DumpBucket(dump_bucket) => run_with_endian(dump_bucket, endian),
-        SubCommand::List(list) => run_with_endian(list, endian),
-        SubCommand::Inspect(inspect) => run_with_endian(inspect, endian),
+    let options = LoadOptions {
+        endian,
+        strict: command.strict,
+        lock,
+    };
+    let result = match command.sub_command {
+        SubCommand::Test(test) => run_with_options(test, &options),
+        SubCommand::Get(get) => run_with_options(get, &options),
+        SubCommand::TraceToGet(trace_to_get) => run_with_options(trace_to_get, &options),
+        SubCommand::DumpBucket(dump_bucket) => run_with_options(dump_bucket, &options),
+        SubCommand::List(list) => run_with_options(list, &options),
+        SubCommand::Header(header) => run_with_options(header, &options),
+        SubCommand::Inform(inform) => run_with_options(inform, &options),
+        SubCommand::Inspect(inspect) => run_with_options(inspect, &options),
+        SubCommand::Export(export) => run_with_options(export, &options),
+        SubCommand::Check(check) => run_with_options(check, &options),
+        SubCommand::Salvage(salvage) => run_with_options(salvage, &options),
+        SubCommand::RecoverWal(recover_wal) => recover_wal.run(),
+        SubCommand::Optimize(optimize) => run_with_options(optimize, &options),
+        SubCommand::Create(create) => create.run(endian.unwrap_or(Endian::Little)),
+        SubCommand::Import(import) => import.run(&options),
+    };
+
+    if let Err(e) = result {
+        eprintln!("rs-tchread: {}", e);
+        process::exit(1);
     }
 }
 
-fn run_with_endian<T: WithPath + Executer>(command: T, endian: Endian) {
-    let path = command.path();
-    match load::open_with_endian(&path, endian) {
+/// How to load databases, given by the global options
+struct LoadOptions {
+    /// Detect the endian if `None`
+    endian: Option<Endian>,
+    strict: bool,
+    /// Take the shared lock to read if any
+    lock: Option<LockMode>,
+}
+
+impl LoadOptions {
+    fn open(&self, path: &Path) -> Result<TCHDBLoaded<binrw::io::BufReader<File>>> {
+        let loaded = match (self.endian, self.lock) {
+            (Some(endian), Some(mode)) => load::open_locked_with_endian(path, endian, mode)?,
+            (Some(endian), None) => load::open_with_endian(path, endian)?,
+            (None, Some(mode)) => load::open_locked(path, mode)?,
+            (None, None) => load::open(path)?,
+        };
+        self.check(loaded)
+    }
+
+    fn open_writable(&self, path: &Path) -> Result<TCHDBLoaded<File>> {
+        let loaded = match self.endian {
+            Some(endian) => load::open_writable_with_endian(path, endian)?,
+            None => load::open_writable(path)?,
+        };
+        self.check(loaded)
+    }
+
+    fn check<R>(&self, loaded: TCHDBLoaded<R>) -> Result<TCHDBLoaded<R>> {
+        let warnings = loaded.warnings();
+
+        if self.strict && !warnings.is_empty() {
+            return Err(Error::Unclean(warnings));
+        }
+        for warning in warnings {
+            eprintln!("rs-tchread: warning: {}", warning);
+        }
+        Ok(loaded)
+    }
+}
+
+fn run_with_options<T: WithPath + Executer>(command: T, options: &LoadOptions) -> Result<()> {
+    let loaded = options.open(command.path())?;
+    // no way to specify an external codec from the command line
+    if loaded.header().options.excodec && command.decodes_values() {
+        return Err(Error::MissingCodec);
+    }
+
+    match loaded {
         TCHDBLoaded::Large(tchdb) => command.execute(tchdb),
         TCHDBLoaded::Small(tchdb) => command.execute(tchdb),
     }
@@ -75,10 +179,22 @@ macro_rules! with_path_impl {
     }
 }
 
-with_path_impl!(Test, Get, TraceToGet, DumpBucket, List, Inspect);
+with_path_impl!(
+    Test, Get, TraceToGet, DumpBucket, List, Header, Inform, Inspect, Export, Check, Salvage,
+    Optimize
+);
 
 trait Executer {
-    fn execute<B: U32orU64, R: Read + Seek>(&self, tchdb: TCHDB<B, R>);
+    fn execute<B: U32orU64, R: Read + Seek + Into<PositionalReader>>(
+        &self,
+        tchdb: TCHDB<B, R>,
+    ) -> Result<()>;
+
+    /// Whether the command decodes values, which needs the codec of a database with
+    /// `HDBTEXCODEC`
+    fn decodes_values(&self) -> bool {
+        true
+    }
 }
 
 #[derive(StructOpt)]
@@ -87,10 +203,13 @@ struct Test {
 }
 
 impl Executer for Test {
-    fn execute<U: U32orU64, R: Read + Seek>(&self, mut tchdb: TCHDB<U, R>) {
+    fn execute<U: U32orU64, R: Read + Seek + Into<PositionalReader>>(
+        &self,
+        mut tchdb: TCHDB<U, R>,
+    ) -> Result<()> {
         println!("{:?}", &tchdb.header);
 
-        let buckets: Buckets<U> = tchdb.read_buckets();
+        let buckets: Buckets<U> = tchdb.read_buckets()?;
         println!("bucket length: {}", buckets.0.len());
         for (i, pos) in buckets
             .0
@@ -109,21 +228,21 @@ impl Executer for Test {
             "free_block_pool offset: {:#01x}",
             tchdb.free_block_pool_offset,
         );
-        for elem in tchdb.read_free_block_pool().into_iter() {
+        for elem in tchdb.read_free_block_pool()?.0 {
             println!(
                 "free_block_pool: offset={:#01x}, size={}",
-                &elem.offset.0 << tchdb.header.alignment_power,
-                &elem.size.0
+                elem.offset, elem.size
             );
         }
 
         let mut tchdb = tchdb.into_multi();
         for record in tchdb.read_record_spaces_multi() {
+            let record = record?;
             println!("{:?}", &record);
             if let RecordSpace::Record(record) = record {
                 let key = tchdb.hash(&record.key);
                 println!("calculated hash: {:?}", key);
-                println!("got record: {:?}", tchdb.get_record(&key));
+                println!("got record: {:?}", tchdb.get_record(&key)?);
             }
         }
         let mut tchdb = tchdb.into_inner();
@@ -131,12 +250,14 @@ impl Executer for Test {
         println!("XXXXXXXXXXXXXXXXXXXXXXXXXXXXXX");
 
         for c in 'a'..='z' {
-            let value = tchdb.get(&c.to_string());
+            let value = tchdb.get(c.to_string())?;
             println!("{:?} => {:?}", c, value);
         }
 
-        let value = tchdb.get("NOT_EXIST");
+        let value = tchdb.get("NOT_EXIST")?;
         println!("NOT_EXIST => {:?}", value);
+
+        Ok(())
     }
 }
 
@@ -145,17 +266,26 @@ impl Executer for Test {
 struct Get {
     path: String,
     key: String,
+    #[structopt(flatten)]
+    key_format: KeyFormat,
+    #[structopt(flatten)]
+    output_format: OutputFormat,
 }
 
 impl Executer for Get {
-    fn execute<U: U32orU64, R: Read + Seek>(&self, mut tchdb: TCHDB<U, R>) {
+    fn execute<U: U32orU64, R: Read + Seek + Into<PositionalReader>>(
+        &self,
+        mut tchdb: TCHDB<U, R>,
+    ) -> Result<()> {
         let stdout = io::stdout().lock();
         let mut stdout = BufWriter::new(stdout);
 
-        if let Some(value) = tchdb.get(&self.key) {
-            stdout.write_all(&value).unwrap();
-            writeln!(stdout, "").unwrap();
+        if let Some(value) = tchdb.get(self.key_format.decode(&self.key)?)? {
+            self.output_format.write_data(&mut stdout, &value)?;
+            self.output_format.write_terminator(&mut stdout)?;
         }
+
+        Ok(())
     }
 }
 
@@ -164,29 +294,111 @@ impl Executer for Get {
 struct TraceToGet {
     path: String,
     key: String,
+    #[structopt(flatten)]
+    key_format: KeyFormat,
+    #[structopt(flatten)]
+    output_format: OutputFormat,
 }
 
 impl Executer for TraceToGet {
-    fn execute<U: U32orU64, R: Read + Seek>(&self, mut tchdb: TCHDB<U, R>) {
+    fn execute<U: U32orU64, R: Read + Seek + Into<PositionalReader>>(
+        &self,
+        mut tchdb: TCHDB<U, R>,
+    ) -> Result<()> {
         let stdout = io::stdout().lock();
         let mut stdout = BufWriter::new(stdout);
 
-        let (key_with_hash, found, visited_records) = tchdb.get_detail(&self.key);
-        writeln!(stdout, "bucket: {}", key_with_hash.idx).unwrap();
-        writeln!(stdout, "hash: {}", key_with_hash.hash).unwrap();
+        let key = self.key_format.decode(&self.key)?;
+        let (key_with_hash, found, visited_records) = tchdb.get_detail(&key)?;
+        let format = &self.output_format;
+        write!(stdout, "bucket: {}", key_with_hash.idx)?;
+        format.write_terminator(&mut stdout)?;
+        write!(stdout, "hash: {}", key_with_hash.hash)?;
+        format.write_terminator(&mut stdout)?;
 
         let len = visited_records.len();
         for (i, mut r) in visited_records.into_iter().enumerate() {
-            write!(stdout, "record {}: hash={}, key=", i + 1, r.hash_value,).unwrap();
-            stdout.write_all(&r.key).unwrap();
-            writeln!(stdout, "").unwrap();
+            write!(stdout, "record {}: hash={}, key=", i + 1, r.hash_value,)?;
+            format.write_data(&mut stdout, &r.key)?;
+            format.write_terminator(&mut stdout)?;
             if found && i == len - 1 {
-                r.value.read_value(&mut tchdb.reader);
+                tchdb.read_value(&mut r)?;
                 let value = r.value.into_value().into_value();
-                stdout.write_all(&value).unwrap();
-                writeln!(stdout, "").unwrap();
+                format.write_data(&mut stdout, &value)?;
+                format.write_terminator(&mut stdout)?;
             }
         }
+
+        Ok(())
+    }
+}
+
+/// How a key is given on the command line, which is taken as it is by default
+#[derive(StructOpt)]
+struct KeyFormat {
+    #[structopt(long, conflicts_with_all(&["key-base64", "key-escaped"]))]
+    /// The key is hexadecimal digits, which may be separated by spaces
+    key_hex: bool,
+    #[structopt(long, conflicts_with("key-escaped"))]
+    /// The key is encoded in base64
+    key_base64: bool,
+    #[structopt(long)]
+    /// The key has escape sequences of C, such as `\t` and `\x00`
+    key_escaped: bool,
+}
+
+impl KeyFormat {
+    fn decode(&self, key: &str) -> Result<Vec<u8>> {
+        if self.key_hex {
+            encoding::decode_hex(key)
+        } else if self.key_base64 {
+            encoding::decode_base64(key)
+        } else if self.key_escaped {
+            encoding::unescape(key)
+        } else {
+            Ok(key.as_bytes().to_vec())
+        }
+    }
+}
+
+/// How keys and values are printed, which are printed as they are by default
+#[derive(StructOpt)]
+struct OutputFormat {
+    #[structopt(long, conflicts_with_all(&["hex", "base64"]))]
+    /// Escape backslashes, tabs, line feeds and carriage returns as `\\`, `\t`, `\n` and
+    /// `\r`, and other bytes outside printable ASCII as `\xNN`, which `--key-escaped` reads
+    escape: bool,
+    #[structopt(long, conflicts_with("base64"))]
+    /// Print keys and values in hexadecimal
+    hex: bool,
+    #[structopt(long)]
+    /// Print keys and values in base64
+    base64: bool,
+    #[structopt(short = "0")]
+    /// Terminate lines by NUL instead of line feeds, and separate keys and values by NUL
+    /// instead of tabs
+    null: bool,
+}
+
+impl OutputFormat {
+    fn write_data(&self, w: &mut impl Write, data: &[u8]) -> io::Result<()> {
+        if self.escape {
+            w.write_all(encoding::escape(data).as_bytes())
+        } else if self.hex {
+            w.write_all(encoding::encode_hex(data).as_bytes())
+        } else if self.base64 {
+            w.write_all(encoding::encode_base64(data).as_bytes())
+        } else {
+            w.write_all(data)
+        }
+    }
+
+    fn write_separator(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(if self.null { b"\0" } else { b"\t" })
+    }
+
+    fn write_terminator(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(if self.null { b"\0" } else { b"\n" })
     }
 }
 
@@ -195,19 +407,30 @@ impl Executer for TraceToGet {
 struct DumpBucket {
     path: String,
     bucket_number: u64,
+    #[structopt(flatten)]
+    output_format: OutputFormat,
 }
 
 impl Executer for DumpBucket {
-    fn execute<U: U32orU64, R: Read + Seek>(&self, mut tchdb: TCHDB<U, R>) {
+    fn decodes_values(&self) -> bool {
+        false
+    }
+
+    fn execute<U: U32orU64, R: Read + Seek + Into<PositionalReader>>(
+        &self,
+        mut tchdb: TCHDB<U, R>,
+    ) -> Result<()> {
         let stdout = io::stdout().lock();
         let mut stdout = BufWriter::new(stdout);
 
-        let records = tchdb.dump_bucket(self.bucket_number);
+        let records = tchdb.dump_bucket(self.bucket_number)?;
         for (i, r) in records.into_iter().enumerate() {
-            write!(stdout, "record {}: hash={}, key=", i + 1, r.hash_value,).unwrap();
-            stdout.write_all(&r.key).unwrap();
-            writeln!(stdout, "").unwrap();
+            write!(stdout, "record {}: hash={}, key=", i + 1, r.hash_value,)?;
+            self.output_format.write_data(&mut stdout, &r.key)?;
+            self.output_format.write_terminator(&mut stdout)?;
         }
+
+        Ok(())
     }
 }
 
@@ -218,116 +441,821 @@ struct List {
     #[structopt(long)]
     /// Print values of records also
     pv: bool,
+    #[structopt(long, short, default_value = "1")]
+    /// The number of threads to read records, which prints records out of order
+    jobs: usize,
+    #[structopt(flatten)]
+    output_format: OutputFormat,
 }
 
 impl Executer for List {
-    fn execute<U: U32orU64, R: Read + Seek>(&self, mut tchdb: TCHDB<U, R>) {
+    fn decodes_values(&self) -> bool {
+        self.pv
+    }
+
+    fn execute<U: U32orU64, R: Read + Seek + Into<PositionalReader>>(
+        &self,
+        mut tchdb: TCHDB<U, R>,
+    ) -> Result<()> {
+        if self.jobs > 1 {
+            let buffers = tchdb.into_concurrent().scan_parallel(
+                self.jobs,
+                self.pv,
+                Vec::new,
+                |buffer, record| {
+                    if let RecordSpace::Record(record) = record {
+                        self.write_record(buffer, record)?;
+                    }
+                    // write lines at once not to be mixed with other threads
+                    if buffer.len() >= PARALLEL_BUFFER_SIZE {
+                        io::stdout().write_all(buffer)?;
+                        buffer.clear();
+                    }
+                    Ok(())
+                },
+            )?;
+            for buffer in buffers {
+                io::stdout().write_all(&buffer)?;
+            }
+            return Ok(());
+        }
+
         let stdout = io::stdout().lock();
         let mut stdout = BufWriter::new(stdout);
 
         for record in tchdb.read_record_spaces(self.pv) {
-            if let RecordSpace::Record(record) = record {
-                stdout.write_all(&record.key).unwrap();
-                if self.pv {
-                    stdout.write_all(b"\t").unwrap();
-                    stdout
-                        .write_all(&record.value.into_value().into_value())
-                        .unwrap();
-                }
-                stdout.write_all(b"\n").unwrap();
+            if let RecordSpace::Record(record) = record? {
+                self.write_record(&mut stdout, record)?;
             }
         }
+
+        Ok(())
+    }
+}
+
+impl List {
+    fn write_record<U: U32orU64>(&self, w: &mut impl Write, record: Record<U>) -> Result<()> {
+        let format = &self.output_format;
+        format.write_data(w, &record.key)?;
+        if self.pv {
+            format.write_separator(w)?;
+            format.write_data(w, &record.value.into_value().into_value())?;
+        }
+        format.write_terminator(w)?;
+        Ok(())
     }
 }
 
+/// Print the fields of the header, including the versions in the magic number
+#[derive(StructOpt)]
+struct Header {
+    path: String,
+}
+
+impl Executer for Header {
+    fn decodes_values(&self) -> bool {
+        false
+    }
+
+    fn execute<U: U32orU64, R: Read + Seek + Into<PositionalReader>>(
+        &self,
+        tchdb: TCHDB<U, R>,
+    ) -> Result<()> {
+        let header = &tchdb.header;
+        let stdout = io::stdout().lock();
+        let mut stdout = BufWriter::new(stdout);
+
+        // the loader has rejected headers without versions
+        if let Some(version) = header.version {
+            writeln!(
+                stdout,
+                "format version: {}.{}",
+                version.format.0, version.format.1
+            )?;
+            writeln!(stdout, "library version: {}", version.library)?;
+        }
+        writeln!(stdout, "endian: {}", endian_name(tchdb.endian))?;
+        writeln!(stdout, "database type: {}", header.database_type)?;
+        writeln!(
+            stdout,
+            "additional flags:{}",
+            spaced(&flag_names(&header.additional_flags))
+        )?;
+        writeln!(stdout, "alignment power: {}", header.alignment_power)?;
+        writeln!(
+            stdout,
+            "free block pool power: {}",
+            header.free_block_pool_power
+        )?;
+        writeln!(stdout, "options:{}", spaced(&option_names(&header.options)))?;
+        writeln!(stdout, "bucket number: {}", header.bucket_number)?;
+        writeln!(stdout, "record number: {}", header.record_number)?;
+        writeln!(stdout, "file size: {}", header.file_size)?;
+        writeln!(stdout, "first record: {:#x}", header.first_record)?;
+
+        Ok(())
+    }
+}
+
+/// Print the information of the database in the same format as `tchmgr inform`
+#[derive(StructOpt)]
+struct Inform {
+    path: String,
+    #[structopt(long)]
+    /// Print a JSON object instead
+    json: bool,
+}
+
+impl Executer for Inform {
+    fn decodes_values(&self) -> bool {
+        false
+    }
+
+    fn execute<U: U32orU64, R: Read + Seek + Into<PositionalReader>>(
+        &self,
+        mut tchdb: TCHDB<U, R>,
+    ) -> Result<()> {
+        let used_bucket_num = {
+            let buckets: Buckets<U> = tchdb.read_buckets()?;
+            buckets.0.into_iter().filter(|b| !b.is_empty()).count()
+        };
+        let header = &tchdb.header;
+        let metadata = fs::metadata(&self.path)?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        let flags = flag_names(&header.additional_flags);
+        let options = option_names(&header.options);
+
+        let stdout = io::stdout().lock();
+        let mut stdout = BufWriter::new(stdout);
+
+        if self.json {
+            let info = serde_json::json!({
+                "path": self.path,
+                "database_type": "hash",
+                "additional_flags": flags,
+                "bucket_number": header.bucket_number,
+                "used_bucket_number": used_bucket_num,
+                "alignment": 1u64 << header.alignment_power,
+                "free_block_pool": 1u64 << header.free_block_pool_power,
+                "inode_number": inode_number(&metadata),
+                "modified_time": w3c_datetime(mtime),
+                "options": options,
+                "record_number": header.record_number,
+                "file_size": header.file_size,
+            });
+            writeln!(stdout, "{}", info)?;
+            return Ok(());
+        }
+
+        writeln!(stdout, "path: {}", self.path)?;
+        writeln!(stdout, "database type: hash")?;
+        writeln!(stdout, "additional flags:{}", spaced(&flags))?;
+        writeln!(stdout, "bucket number: {}", header.bucket_number)?;
+        writeln!(stdout, "used bucket number: {}", used_bucket_num)?;
+        writeln!(stdout, "alignment: {}", 1u64 << header.alignment_power)?;
+        writeln!(
+            stdout,
+            "free block pool: {}",
+            1u64 << header.free_block_pool_power
+        )?;
+        writeln!(stdout, "inode number: {}", inode_number(&metadata))?;
+        writeln!(stdout, "modified time: {}", w3c_datetime(mtime))?;
+        writeln!(stdout, "options:{}", spaced(&options))?;
+        writeln!(stdout, "record number: {}", header.record_number)?;
+        writeln!(stdout, "file size: {}", header.file_size)?;
+
+        Ok(())
+    }
+}
+
+/// Lists are printed with a leading space for each element as `tchmgr` does
+fn spaced(names: &[&str]) -> String {
+    names.iter().map(|name| format!(" {}", name)).collect()
+}
+
+/// The names of `HDBFOPEN` and `HDBFFATAL` as `tchmgr` prints
+fn flag_names(flags: &AdditionalFlags) -> Vec<&'static str> {
+    [(flags.open, "open"), (flags.fatal, "fatal")]
+        .into_iter()
+        .filter_map(|(set, name)| set.then_some(name))
+        .collect()
+}
+
+#[cfg(unix)]
+fn inode_number(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.ino()
+}
+
+#[cfg(not(unix))]
+fn inode_number(_metadata: &fs::Metadata) -> u64 {
+    0
+}
+
+/// Format seconds since the epoch in the local time zone as `tcdatestrwww` does, like
+/// `2010-01-01T00:00:00+09:00`, or `2009-12-31T15:00:00Z` in UTC
+fn w3c_datetime(secs: i64) -> String {
+    let offset = utc_offset(secs);
+    let local = secs + offset;
+    let days = local.div_euclid(86400);
+    let secs = local.rem_euclid(86400);
+
+    // the civil date from days since the epoch, by Howard Hinnant's algorithm
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    let zone = if offset == 0 {
+        "Z".to_string()
+    } else {
+        let sign = if offset < 0 { '-' } else { '+' };
+        let minutes = offset.abs() / 60;
+        format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+    };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        zone
+    )
+}
+
+/// The offset of the local time zone from UTC in seconds at the time
+#[cfg(unix)]
+fn utc_offset(secs: i64) -> i64 {
+    let time = secs as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return 0;
+    }
+    tm.tm_gmtoff as i64
+}
+
+#[cfg(not(unix))]
+fn utc_offset(_secs: i64) -> i64 {
+    0
+}
+
+fn endian_name(endian: Endian) -> &'static str {
+    match endian {
+        Endian::Big => "big",
+        Endian::Little => "little",
+    }
+}
+
+/// The names of the enabled options as `tchmgr` prints
+fn option_names(options: &Options) -> Vec<&'static str> {
+    [
+        (options.large, "large"),
+        (options.deflate, "deflate"),
+        (options.bzip, "bzip"),
+        (options.tcbs, "tcbs"),
+        (options.excodec, "excodec"),
+    ]
+    .into_iter()
+    .filter_map(|(enabled, name)| enabled.then_some(name))
+    .collect()
+}
+
 /// Traverse through and stat all records
 #[derive(StructOpt)]
 struct Inspect {
     path: String,
+    #[structopt(long, short, default_value = "1")]
+    /// The number of threads to read records
+    jobs: usize,
 }
 
 impl Executer for Inspect {
-    fn execute<U: U32orU64, R: Read + Seek>(&self, mut tchdb: TCHDB<U, R>) {
+    fn decodes_values(&self) -> bool {
+        false
+    }
+
+    fn execute<U: U32orU64, R: Read + Seek + Into<PositionalReader>>(
+        &self,
+        mut tchdb: TCHDB<U, R>,
+    ) -> Result<()> {
+        let endian = tchdb.endian;
+        let flags = tchdb.header.additional_flags;
         let bucket_num;
         let empty_bucket_num;
         {
-            let buckets: Buckets<U> = tchdb.read_buckets();
+            let buckets: Buckets<U> = tchdb.read_buckets()?;
             bucket_num = buckets.0.len();
             empty_bucket_num = buckets.0.into_iter().filter(|b| b.is_empty()).count();
         }
 
-        let mut record_num = 0u64;
-        let mut record_no_children = 0u64;
-        let mut record_one_child = 0u64;
-        let mut record_two_children = 0u64;
-        let mut key_length = 0.0f64;
-        let mut value_length = 0.0f64;
-        let mut padding_length = 0.0f64;
-        let mut freeblock_num = 0u64;
-        for record in tchdb.read_record_spaces(false) {
-            match record {
-                RecordSpace::Record(record) => {
-                    record_num += 1;
-                    key_length += record.key_size.0 as f64;
-                    value_length += record.value_size.0 as f64;
-                    padding_length += record.padding_size as f64;
-                    if record.right_chain.is_empty() {
-                        if record.left_chain.is_empty() {
-                            record_no_children += 1;
-                        } else {
-                            record_one_child += 1;
-                        }
-                    } else {
-                        if record.left_chain.is_empty() {
-                            record_one_child += 1;
-                        } else {
-                            record_two_children += 1;
-                        }
-                    }
-                }
-                RecordSpace::FreeBlock(_) => {
-                    freeblock_num += 1;
-                }
+        let mut stats = RecordStats::default();
+        if self.jobs > 1 {
+            for s in tchdb.into_concurrent().scan_parallel(
+                self.jobs,
+                false,
+                RecordStats::default,
+                |stats, record| {
+                    stats.add(record);
+                    Ok(())
+                },
+            )? {
+                stats.merge(s);
+            }
+        } else {
+            for record in tchdb.read_record_spaces(false) {
+                stats.add(record?);
             }
         }
+        let RecordStats {
+            record_num,
+            record_no_children,
+            record_one_child,
+            record_two_children,
+            key_length,
+            value_length,
+            padding_length,
+            freeblock_num,
+        } = stats;
 
         let stdout = io::stdout().lock();
         let mut stdout = BufWriter::new(stdout);
 
-        writeln!(stdout, "# of buckets: {}", bucket_num).unwrap();
-        writeln!(stdout, "# of empty buckets: {}", empty_bucket_num).unwrap();
-        writeln!(stdout, "# of records: {}", record_num).unwrap();
+        writeln!(stdout, "endian: {}", endian_name(endian))?;
+        writeln!(stdout, "additional flags:{}", spaced(&flag_names(&flags)))?;
+        writeln!(stdout, "# of buckets: {}", bucket_num)?;
+        writeln!(stdout, "# of empty buckets: {}", empty_bucket_num)?;
+        writeln!(stdout, "# of records: {}", record_num)?;
         writeln!(
             stdout,
             "# of records without children: {}",
             record_no_children
-        )
-        .unwrap();
-        writeln!(stdout, "# of records with one child: {}", record_one_child).unwrap();
+        )?;
+        writeln!(stdout, "# of records with one child: {}", record_one_child)?;
         writeln!(
             stdout,
             "# of records with two children: {}",
             record_two_children
-        )
-        .unwrap();
+        )?;
         writeln!(
             stdout,
             "avg of key length: {}",
             key_length / record_num as f64
-        )
-        .unwrap();
+        )?;
         writeln!(
             stdout,
             "avg of value length: {}",
             value_length / record_num as f64
-        )
-        .unwrap();
+        )?;
         writeln!(
             stdout,
             "avg of padding length: {}",
             padding_length / record_num as f64
-        )
-        .unwrap();
-        writeln!(stdout, "# of free blocks: {}", freeblock_num).unwrap();
+        )?;
+        writeln!(stdout, "# of free blocks: {}", freeblock_num)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct RecordStats {
+    record_num: u64,
+    record_no_children: u64,
+    record_one_child: u64,
+    record_two_children: u64,
+    key_length: f64,
+    value_length: f64,
+    padding_length: f64,
+    freeblock_num: u64,
+}
+
+impl RecordStats {
+    fn add<U: U32orU64>(&mut self, record: RecordSpace<U>) {
+        match record {
+            RecordSpace::Record(record) => {
+                self.record_num += 1;
+                self.key_length += record.key_size.0 as f64;
+                self.value_length += record.value_size.0 as f64;
+                self.padding_length += record.padding_size as f64;
+                if record.right_chain.is_empty() {
+                    if record.left_chain.is_empty() {
+                        self.record_no_children += 1;
+                    } else {
+                        self.record_one_child += 1;
+                    }
+                } else {
+                    if record.left_chain.is_empty() {
+                        self.record_one_child += 1;
+                    } else {
+                        self.record_two_children += 1;
+                    }
+                }
+            }
+            RecordSpace::FreeBlock(_) => {
+                self.freeblock_num += 1;
+            }
+        }
+    }
+
+    fn merge(&mut self, other: RecordStats) {
+        self.record_num += other.record_num;
+        self.record_no_children += other.record_no_children;
+        self.record_one_child += other.record_one_child;
+        self.record_two_children += other.record_two_children;
+        self.key_length += other.key_length;
+        self.value_length += other.value_length;
+        self.padding_length += other.padding_length;
+        self.freeblock_num += other.freeblock_num;
+    }
+}
+
+/// Print all records in a format for other tools
+#[derive(StructOpt)]
+struct Export {
+    path: String,
+    #[structopt(long, default_value = "jsonl", possible_values(&["jsonl", "csv", "msgpack"]))]
+    /// The format of records
+    format: String,
+    #[structopt(long, default_value = "utf8", possible_values(DATA_ENCODINGS))]
+    /// The encoding of keys, utf8 falls back to base64 for invalid strings
+    key_encoding: String,
+    #[structopt(long, default_value = "utf8", possible_values(DATA_ENCODINGS))]
+    /// The encoding of values, utf8 falls back to base64 for invalid strings
+    value_encoding: String,
+    #[structopt(long)]
+    /// Add offsets of records
+    offset: bool,
+    #[structopt(long)]
+    /// Add hash values of records
+    hash: bool,
+    #[structopt(long, short, default_value = "1")]
+    /// The number of threads to read records, which prints records out of order
+    jobs: usize,
+}
+
+const DATA_ENCODINGS: &[&str] = &["utf8", "base64", "hex"];
+
+impl Executer for Export {
+    fn execute<U: U32orU64, R: Read + Seek + Into<PositionalReader>>(
+        &self,
+        mut tchdb: TCHDB<U, R>,
+    ) -> Result<()> {
+        let format = match self.format.as_str() {
+            "csv" => export::Format::Csv,
+            "msgpack" => export::Format::MessagePack,
+            _ => export::Format::JsonLines,
+        };
+        let exporter = Exporter::new(format)
+            .key_encoding(data_encoding(&self.key_encoding))
+            .value_encoding(data_encoding(&self.value_encoding))
+            .offset(self.offset)
+            .hash(self.hash);
+
+        if self.jobs > 1 {
+            exporter.write_header(&mut io::stdout())?;
+            let buffers = tchdb.into_concurrent().scan_parallel(
+                self.jobs,
+                true,
+                Vec::new,
+                |buffer, record| {
+                    if let RecordSpace::Record(record) = record {
+                        exporter.write_record(buffer, record)?;
+                    }
+                    if buffer.len() >= PARALLEL_BUFFER_SIZE {
+                        io::stdout().write_all(buffer)?;
+                        buffer.clear();
+                    }
+                    Ok(())
+                },
+            )?;
+            for buffer in buffers {
+                io::stdout().write_all(&buffer)?;
+            }
+            return Ok(());
+        }
+
+        let stdout = io::stdout().lock();
+        exporter.export(&mut tchdb, BufWriter::new(stdout))?;
+
+        Ok(())
+    }
+}
+
+fn data_encoding(name: &str) -> DataEncoding {
+    match name {
+        "base64" => DataEncoding::Base64,
+        "hex" => DataEncoding::Hex,
+        _ => DataEncoding::Utf8,
+    }
+}
+
+/// The exit status of `check` when the file is read but discrepancies are found
+const EXIT_DISCREPANCIES: i32 = 2;
+
+/// Check the consistency of the file, and print discrepancies as tab separated lines of
+/// the kind, the offset and the description. Exit with 2 if any discrepancy is found, or 1 if
+/// the file can't be read at all.
+#[derive(StructOpt)]
+struct Check {
+    path: String,
+}
+
+impl Executer for Check {
+    fn decodes_values(&self) -> bool {
+        false
+    }
+
+    fn execute<U: U32orU64, R: Read + Seek + Into<PositionalReader>>(
+        &self,
+        mut tchdb: TCHDB<U, R>,
+    ) -> Result<()> {
+        let findings = tchdb.verify()?;
+        {
+            let stdout = io::stdout().lock();
+            let mut stdout = BufWriter::new(stdout);
+            for finding in &findings {
+                let offset = match finding.offset() {
+                    Some(offset) => format!("{:#x}", offset),
+                    None => "-".to_string(),
+                };
+                writeln!(stdout, "{}\t{}\t{}", finding.kind(), offset, finding)?;
+            }
+            stdout.flush()?;
+        }
+
+        if !findings.is_empty() {
+            process::exit(EXIT_DISCREPANCIES);
+        }
+        Ok(())
+    }
+}
+
+/// Print keys and values of all records which can be read, skipping corrupt regions.
+/// Skipped byte ranges are reported to stderr.
+#[derive(StructOpt)]
+struct Salvage {
+    path: String,
+}
+
+impl Executer for Salvage {
+    fn execute<U: U32orU64, R: Read + Seek + Into<PositionalReader>>(
+        &self,
+        mut tchdb: TCHDB<U, R>,
+    ) -> Result<()> {
+        let stdout = io::stdout().lock();
+        let mut stdout = BufWriter::new(stdout);
+
+        let mut record_num = 0u64;
+        let mut skipped_size = 0u64;
+        for salvaged in tchdb.salvage()? {
+            match salvaged? {
+                Salvaged::Record(record) => {
+                    record_num += 1;
+                    stdout.write_all(&record.key)?;
+                    stdout.write_all(b"\t")?;
+                    stdout.write_all(&record.value.into_value().into_value())?;
+                    stdout.write_all(b"\n")?;
+                }
+                Salvaged::Skipped { offset, size } => {
+                    skipped_size += size;
+                    eprintln!(
+                        "skipped: {:#x}-{:#x} ({} bytes)",
+                        offset,
+                        offset + size,
+                        size
+                    );
+                }
+            }
+        }
+        stdout.flush()?;
+
+        eprintln!(
+            "{} records salvaged, {} bytes skipped",
+            record_num, skipped_size
+        );
+
+        Ok(())
+    }
+}
+
+/// Write the database as it was before the interrupted transaction into another file, by
+/// applying the write-ahead log `<path>.wal` as tokyo cabinet does on opening.
+/// Neither the database nor the log is modified.
+#[derive(StructOpt)]
+struct RecoverWal {
+    path: String,
+    dst: String,
+}
+
+impl RecoverWal {
+    fn run(&self) -> Result<()> {
+        if !wal::is_pending(&self.path)? {
+            eprintln!(
+                "no write-ahead log of an interrupted transaction: {}",
+                wal::wal_path(&self.path).display()
+            );
+            return Ok(());
+        }
+
+        let mut dst = BufWriter::new(File::create(&self.dst)?);
+        wal::recover(&self.path, &mut dst)?;
+        Ok(())
+    }
+}
+
+/// Rewrite all records into a new file without free blocks, as `tchmgr optimize` does.
+/// The parameters which are not given are the same as the source, except that the bucket
+/// number is twice the number of records. If any of the options is given, the others are
+/// cleared.
+#[derive(StructOpt)]
+struct Optimize {
+    #[structopt(long = "tl")]
+    /// Enable the option `HDBTLARGE`
+    large: bool,
+    #[structopt(long = "td", conflicts_with_all(&["bzip", "tcbs"]))]
+    /// Enable the option `HDBTDEFLATE`
+    deflate: bool,
+    #[structopt(long = "tb", conflicts_with("tcbs"))]
+    /// Enable the option `HDBTBZIP`
+    bzip: bool,
+    #[structopt(long = "tt")]
+    /// Enable the option `HDBTTCBS`
+    tcbs: bool,
+    #[structopt(long = "tz", conflicts_with_all(&["large", "deflate", "bzip", "tcbs"]))]
+    /// Clear all options
+    no_options: bool,
+    path: String,
+    /// The file to write, which is truncated
+    dst: String,
+    /// The number of elements of the bucket array
+    bucket_number: Option<u64>,
+    /// The power of 2 of the record alignment
+    alignment_power: Option<u8>,
+    /// The power of 2 of the maximum number of elements of the free block pool
+    free_block_pool_power: Option<u8>,
+}
+
+impl Executer for Optimize {
+    fn execute<U: U32orU64, R: Read + Seek + Into<PositionalReader>>(
+        &self,
+        mut tchdb: TCHDB<U, R>,
+    ) -> Result<()> {
+        // creating the destination truncates the source if they are the same
+        if let Ok(dst) = fs::canonicalize(&self.dst) {
+            if dst == fs::canonicalize(&self.path)? {
+                return Err(Error::Io(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the destination is the source database",
+                )));
+            }
+        }
+
+        let mut builder = tchdb.optimize_builder();
+        if self.large || self.deflate || self.bzip || self.tcbs || self.no_options {
+            builder = builder.options(Options {
+                large: self.large,
+                deflate: self.deflate,
+                bzip: self.bzip,
+                tcbs: self.tcbs,
+                excodec: false,
+            });
+        }
+        if let Some(bucket_number) = self.bucket_number {
+            builder = builder.bucket_number(bucket_number);
+        }
+        if let Some(alignment_power) = self.alignment_power {
+            builder = builder.alignment_power(alignment_power);
+        }
+        if let Some(free_block_pool_power) = self.free_block_pool_power {
+            builder = builder.free_block_pool_power(free_block_pool_power);
+        }
+
+        tchdb.optimize(&builder, &self.dst)?;
+
+        Ok(())
+    }
+}
+
+/// Create a database file, as `tchmgr create` does
+#[derive(StructOpt)]
+struct Create {
+    #[structopt(long = "tl")]
+    /// Enable the option `HDBTLARGE`
+    large: bool,
+    #[structopt(long = "td", conflicts_with_all(&["bzip", "tcbs"]))]
+    /// Enable the option `HDBTDEFLATE`
+    deflate: bool,
+    #[structopt(long = "tb", conflicts_with("tcbs"))]
+    /// Enable the option `HDBTBZIP`
+    bzip: bool,
+    #[structopt(long = "tt")]
+    /// Enable the option `HDBTTCBS`
+    tcbs: bool,
+    path: String,
+    /// The number of elements of the bucket array
+    bucket_number: Option<u64>,
+    /// The power of 2 of the record alignment
+    alignment_power: Option<u8>,
+    /// The power of 2 of the maximum number of elements of the free block pool
+    free_block_pool_power: Option<u8>,
+}
+
+impl Create {
+    fn run(&self, endian: Endian) -> Result<()> {
+        let mut builder = TCHDBBuilder::new().endian(endian).options(Options {
+            large: self.large,
+            deflate: self.deflate,
+            bzip: self.bzip,
+            tcbs: self.tcbs,
+            excodec: false,
+        });
+        if let Some(bucket_number) = self.bucket_number {
+            builder = builder.bucket_number(bucket_number);
+        }
+        if let Some(alignment_power) = self.alignment_power {
+            builder = builder.alignment_power(alignment_power);
+        }
+        if let Some(free_block_pool_power) = self.free_block_pool_power {
+            builder = builder.free_block_pool_power(free_block_pool_power);
+        }
+
+        builder.create(&self.path)?;
+
+        Ok(())
+    }
+}
+
+/// Store records read from a file or the standard input, as `tchmgr importtsv` does.
+/// The database is created if it doesn't exist, with buckets twice as many as the records.
+#[derive(StructOpt)]
+struct Import {
+    #[structopt(long, default_value = "tsv", possible_values(&["tsv", "jsonl"]))]
+    /// The format of records
+    format: String,
+    #[structopt(long, default_value = "utf8", possible_values(DATA_ENCODINGS))]
+    /// The encoding of keys, utf8 takes keys as they are
+    key_encoding: String,
+    #[structopt(long, default_value = "utf8", possible_values(DATA_ENCODINGS))]
+    /// The encoding of values, utf8 takes values as they are
+    value_encoding: String,
+    path: String,
+    /// The file to read records, or the standard input if omitted
+    file: Option<String>,
+}
+
+impl Import {
+    fn run(&self, options: &LoadOptions) -> Result<()> {
+        let format = match self.format.as_str() {
+            "jsonl" => import::Format::JsonLines,
+            _ => import::Format::Tsv,
+        };
+        let importer = Importer::new(format)
+            .key_encoding(data_encoding(&self.key_encoding))
+            .value_encoding(data_encoding(&self.value_encoding));
+
+        match &self.file {
+            Some(file) => self.import(&importer, options, || Ok(BufReader::new(File::open(file)?))),
+            None => {
+                // read twice to count records
+                let mut input = Vec::new();
+                io::stdin().lock().read_to_end(&mut input)?;
+                self.import(&importer, options, || Ok(Cursor::new(&input)))
+            }
+        }
+    }
+
+    fn import<B: BufRead>(
+        &self,
+        importer: &Importer,
+        options: &LoadOptions,
+        input: impl Fn() -> Result<B>,
+    ) -> Result<()> {
+        let path = Path::new(&self.path);
+        let loaded = if path.exists() {
+            options.open_writable(path)?
+        } else {
+            let record_num = importer.count(input()?)?;
+            TCHDBBuilder::new()
+                .endian(options.endian.unwrap_or(Endian::Little))
+                .bucket_number(record_num * 2)
+                .create(path)?
+        };
+
+        match loaded {
+            TCHDBLoaded::Large(mut tchdb) => importer.import(&mut tchdb, input()?)?,
+            TCHDBLoaded::Small(mut tchdb) => importer.import(&mut tchdb, input()?)?,
+        };
+
+        Ok(())
     }
 }
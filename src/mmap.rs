@@ -0,0 +1,115 @@
+use std::{
+    borrow::Cow,
+    io::{Cursor, Seek, SeekFrom},
+    mem,
+};
+
+use binrw::BinReaderExt;
+use memmap2::Mmap;
+
+use crate::{
+    binrw_types::{Record, RecordOffset, U32orU64},
+    compression::Compression,
+    tree, Error, KeyWithHash, Result, TCHDB,
+};
+
+/// A database mapped in memory, which is also read through the `Read + Seek` methods
+pub type TCHDBMmap<U> = TCHDB<U, Cursor<Mmap>>;
+
+/// Lookups without seeking nor copying, for databases in memory
+impl<U: U32orU64, M: AsRef<[u8]>> TCHDB<U, Cursor<M>> {
+    #[inline]
+    fn data(&self) -> &[u8] {
+        self.reader.get_ref().as_ref()
+    }
+
+    /// The bucket array as a slice of the memory, or an error if it exceeds the end of file
+    pub fn bucket_slice(&self) -> Result<&[u8]> {
+        let start = self.bucket_offset as usize;
+        usize::try_from(self.header.bucket_number)
+            .ok()
+            .and_then(|number| number.checked_mul(mem::size_of::<U>()))
+            .and_then(|len| start.checked_add(len))
+            .and_then(|end| self.data().get(start..end))
+            .ok_or(Error::CorruptBucketArray(binrw::Error::AssertFail {
+                pos: self.bucket_offset,
+                message: "the bucket array exceeds the end of file".to_string(),
+            }))
+    }
+
+    fn record_at(&self, offset: u64) -> Result<Record<U>> {
+        let mut cursor = Cursor::new(self.data());
+        cursor.seek(SeekFrom::Start(offset))?;
+        let record_space = cursor
+            .read_type_args(self.endian, (offset, false, Compression::None))
+            .map_err(Error::corrupt_record(offset))?;
+        tree::expect_record(offset, record_space)
+    }
+
+    fn bucket_at(&self, idx: u64) -> Result<RecordOffset<U>> {
+        let mut cursor = Cursor::new(self.bucket_slice()?);
+        cursor.seek(SeekFrom::Start(mem::size_of::<U>() as u64 * idx))?;
+        cursor
+            .read_type(self.endian)
+            .map_err(Error::CorruptBucketArray)
+    }
+
+    /// Find a record like `get_record`, without reading its value
+    pub fn get_record_ref(&self, key: &KeyWithHash) -> Result<Option<Record<U>>> {
+        let bucket = self.bucket_at(key.idx)?;
+        let (found, mut records) =
+            tree::search(key, bucket, self.header.alignment_power, |offset| {
+                self.record_at(offset)
+            })?;
+        Ok(if found { records.pop() } else { None })
+    }
+
+    /// The value of the record borrowed from the memory.
+    /// Values of compressed databases are decompressed into a new buffer.
+    pub fn value_ref(&self, record: &Record<U>) -> Result<Cow<'_, [u8]>> {
+        let start = record.value_offset() as usize;
+        let data = start
+            .checked_add(record.value_size.0 as usize)
+            .and_then(|end| self.data().get(start..end))
+            .ok_or(Error::CorruptRecord {
+                offset: record.space_offset(),
+                source: binrw::Error::AssertFail {
+                    pos: start as u64,
+                    message: "the value exceeds the end of file".to_string(),
+                },
+            })?;
+
+        match self.header.options.compression() {
+            Compression::None => Ok(Cow::Borrowed(data)),
+            Compression::External => {
+                let codec = self.codec.as_deref().ok_or(Error::MissingCodec)?;
+                let value = codec.decode(data).map_err(|source| Error::Codec {
+                    offset: record.space_offset(),
+                    source,
+                })?;
+                Ok(Cow::Owned(value))
+            }
+            compression => {
+                let value = compression.decompress(data, self.endian).map_err(|err| {
+                    Error::CorruptRecord {
+                        offset: record.space_offset(),
+                        source: binrw::Error::Custom {
+                            pos: start as u64,
+                            err: Box::new(err),
+                        },
+                    }
+                })?;
+                Ok(Cow::Owned(value))
+            }
+        }
+    }
+
+    /// Get the value like `get`, borrowing it from the memory if not compressed
+    pub fn get_ref<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Cow<'_, [u8]>>> {
+        let key = self.hash(key.as_ref());
+        match self.get_record_ref(&key)? {
+            None => Ok(None),
+            Some(record) => self.value_ref(&record).map(Some),
+        }
+    }
+}
@@ -7,7 +7,7 @@ use std::{
 
 use binrw::{BinReaderExt, Endian};
 
-use crate::binrw_types::U32orU64;
+use crate::{binrw_types::U32orU64, compression::Compression, Error, Result};
 
 use super::{Header, RecordSpace, TCHDB};
 
@@ -52,6 +52,10 @@ impl<U, R> TCHDB<U, R> {
             header: self.header,
             bucket_offset: self.bucket_offset,
             free_block_pool_offset: self.free_block_pool_offset,
+            codec: self.codec,
+            tran: self.tran,
+            pending_wal: self.pending_wal,
+            path: self.path,
             bucket_type: self.bucket_type,
         }
     }
@@ -65,6 +69,10 @@ impl<U, R> TCHDB<U, MultiRead<R>> {
             header: self.header,
             bucket_offset: self.bucket_offset,
             free_block_pool_offset: self.free_block_pool_offset,
+            codec: self.codec,
+            tran: self.tran,
+            pending_wal: self.pending_wal,
+            path: self.path,
             bucket_type: self.bucket_type,
         }
     }
@@ -78,6 +86,7 @@ impl<U, R: Clone> TCHDB<U, R> {
 pub struct RecordSpaceMultiIter<U, R> {
     reader: R,
     endian: Endian,
+    compression: Compression,
     file_size: u64,
     next_pos: u64,
     _bucket_type: PhantomData<U>,
@@ -88,6 +97,7 @@ impl<U, R> RecordSpaceMultiIter<U, R> {
         RecordSpaceMultiIter {
             reader,
             endian,
+            compression: header.options.compression(),
             file_size: header.file_size,
             next_pos: header.first_record,
             _bucket_type: PhantomData,
@@ -96,26 +106,36 @@ impl<U, R> RecordSpaceMultiIter<U, R> {
 }
 
 impl<U: U32orU64, R: Read + Seek> Iterator for RecordSpaceMultiIter<U, R> {
-    type Item = RecordSpace<U>;
+    type Item = Result<RecordSpace<U>>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.next_pos >= self.file_size {
             return None;
         }
 
-        self.reader.seek(SeekFrom::Start(self.next_pos)).unwrap();
+        let result = self.read_next();
+        if result.is_err() {
+            self.next_pos = self.file_size;
+        }
+        Some(result)
+    }
+}
+
+impl<U: U32orU64, R: Read + Seek> RecordSpaceMultiIter<U, R> {
+    fn read_next(&mut self) -> Result<RecordSpace<U>> {
+        self.reader.seek(SeekFrom::Start(self.next_pos))?;
         match self
             .reader
-            .read_type_args(self.endian, (self.next_pos, false))
-            .unwrap()
+            .read_type_args(self.endian, (self.next_pos, false, self.compression))
+            .map_err(Error::corrupt_record(self.next_pos))?
         {
             RecordSpace::FreeBlock(free_block) => {
-                self.next_pos = self.reader.stream_position().unwrap();
-                Some(RecordSpace::FreeBlock(free_block))
+                self.next_pos = self.reader.stream_position()?;
+                Ok(RecordSpace::FreeBlock(free_block))
             }
             RecordSpace::Record(record) => {
                 self.next_pos = record.next_record();
-                Some(RecordSpace::Record(record))
+                Ok(RecordSpace::Record(record))
             }
         }
     }
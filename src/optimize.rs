@@ -0,0 +1,68 @@
+use std::{
+    fs::File,
+    io::{Read, Seek},
+    path::Path,
+};
+
+use crate::{
+    binrw_types::{RecordSpace, U32orU64},
+    builder::DEFAULT_BUCKET_NUMBER,
+    load::TCHDBLoaded,
+    Result, TCHDBBuilder, TCHDB,
+};
+
+impl<U, R> TCHDB<U, R> {
+    /// The parameters which `tchdboptimize` chooses by default. The bucket number is twice the
+    /// number of records but not less than the default, and the others are the same as this
+    /// database.
+    pub fn optimize_builder(&self) -> TCHDBBuilder {
+        TCHDBBuilder::new()
+            .endian(self.endian)
+            .bucket_number(
+                (self
+                    .header
+                    .record_number
+                    .saturating_mul(2)
+                    .saturating_add(1))
+                .max(DEFAULT_BUCKET_NUMBER),
+            )
+            .alignment_power(self.header.alignment_power)
+            .free_block_pool_power(self.header.free_block_pool_power)
+            .options(self.header.options)
+    }
+}
+
+impl<U: U32orU64, R: Read + Seek> TCHDB<U, R> {
+    /// Rewrite all records into a new database at `path` created by `builder`, which has
+    /// neither free blocks nor padding except for the alignment. Values are compressed again
+    /// by the options of `builder`, and the opaque region of the header is kept as
+    /// `tchdboptimize` does. The file at `path` is truncated, so it must not be this database.
+    pub fn optimize<T: AsRef<Path>>(
+        &mut self,
+        builder: &TCHDBBuilder,
+        path: T,
+    ) -> Result<TCHDBLoaded<File>> {
+        let mut loaded = builder.create(path)?;
+        match &mut loaded {
+            TCHDBLoaded::Large(dst) => self.copy_records(dst)?,
+            TCHDBLoaded::Small(dst) => self.copy_records(dst)?,
+        }
+
+        Ok(loaded)
+    }
+
+    fn copy_records<V: U32orU64>(&mut self, dst: &mut TCHDB<V, File>) -> Result<()> {
+        dst.header.opaque_region = self.header.opaque_region.clone();
+        dst.write_header()?;
+
+        for record in self.read_record_spaces(true) {
+            if let RecordSpace::Record(record) = record? {
+                let value = record.value.into_value().into_value();
+                dst.put(&record.key, value)?;
+            }
+        }
+        dst.reader.sync_data()?;
+
+        Ok(())
+    }
+}
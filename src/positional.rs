@@ -0,0 +1,207 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    mem,
+};
+
+use binrw::{io::BufReader, BinReaderExt};
+
+use crate::{
+    binrw_types::{Buckets, Record, RecordOffset, U32orU64},
+    compression::Compression,
+    decode_value, tree, Error, KeyWithHash, Result, TCHDB,
+};
+
+/// Large enough for most records except their values
+const BUFFER_SIZE: usize = 512;
+
+/// A file read only by positional reads, which has no position shared between threads
+#[derive(Debug)]
+pub struct PositionalReader(File);
+
+/// A database which can be shared between threads, whose lookups take `&self`
+pub type TCHDBConcurrent<U> = TCHDB<U, PositionalReader>;
+
+impl PositionalReader {
+    pub fn new(file: File) -> Self {
+        PositionalReader(file)
+    }
+
+    pub fn into_inner(self) -> File {
+        self.0
+    }
+
+    /// A buffered reader which starts from `pos` and has its own position
+    #[inline]
+    fn reader_at(&self, pos: u64) -> BufReader<ReadAt<'_>> {
+        self.reader_with_capacity(pos, BUFFER_SIZE)
+    }
+
+    pub(crate) fn reader_with_capacity(&self, pos: u64, capacity: usize) -> BufReader<ReadAt<'_>> {
+        BufReader::with_capacity(capacity, ReadAt { file: &self.0, pos })
+    }
+}
+
+impl From<File> for PositionalReader {
+    #[inline]
+    fn from(file: File) -> Self {
+        PositionalReader(file)
+    }
+}
+
+/// The buffer is discarded, as positional reads don't depend on the position
+impl From<BufReader<File>> for PositionalReader {
+    #[inline]
+    fn from(reader: BufReader<File>) -> Self {
+        PositionalReader(reader.into_inner())
+    }
+}
+
+pub(crate) struct ReadAt<'a> {
+    file: &'a File,
+    pos: u64,
+}
+
+impl Read for ReadAt<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = read_at(self.file, buf, self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for ReadAt<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(diff) => self.pos.checked_add_signed(diff),
+            SeekFrom::End(diff) => self.file.metadata()?.len().checked_add_signed(diff),
+        };
+        self.pos = pos.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(unix)]
+#[inline]
+fn read_at(file: &File, buf: &mut [u8], pos: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, pos)
+}
+
+#[cfg(windows)]
+#[inline]
+fn read_at(file: &File, buf: &mut [u8], pos: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, pos)
+}
+
+impl<U, R: Into<PositionalReader>> TCHDB<U, R> {
+    pub fn into_concurrent(self) -> TCHDBConcurrent<U> {
+        TCHDB {
+            reader: self.reader.into(),
+            endian: self.endian,
+            header: self.header,
+            bucket_offset: self.bucket_offset,
+            free_block_pool_offset: self.free_block_pool_offset,
+            codec: self.codec,
+            tran: self.tran,
+            pending_wal: self.pending_wal,
+            path: self.path,
+            bucket_type: self.bucket_type,
+        }
+    }
+}
+
+/// Lookups by positional reads, which can be called from many threads at once
+impl<U: U32orU64> TCHDBConcurrent<U> {
+    pub fn read_buckets(&self) -> Result<Buckets<U>> {
+        self.reader
+            .reader_at(self.bucket_offset)
+            .read_type_args(self.endian, (self.header.bucket_number,))
+            .map_err(Error::CorruptBucketArray)
+    }
+
+    fn read_bucket(&self, idx: u64) -> Result<RecordOffset<U>> {
+        let pos = self.bucket_offset + mem::size_of::<U>() as u64 * idx;
+        self.reader
+            .reader_at(pos)
+            .read_type(self.endian)
+            .map_err(Error::CorruptBucketArray)
+    }
+
+    fn read_record_at(&self, offset: u64) -> Result<Record<U>> {
+        let record_space = self
+            .reader
+            .reader_at(offset)
+            .read_type_args(
+                self.endian,
+                (offset, false, self.header.options.compression()),
+            )
+            .map_err(Error::corrupt_record(offset))?;
+        tree::expect_record(offset, record_space)
+    }
+
+    pub fn get_record(&self, key: &KeyWithHash) -> Result<Option<Record<U>>> {
+        let (found, mut log) = self.get_record_detail(key)?;
+        if found {
+            Ok(log.pop())
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn get_record_detail(&self, key: &KeyWithHash) -> Result<(bool, Vec<Record<U>>)> {
+        let bucket = self.read_bucket(key.idx)?;
+        tree::search(key, bucket, self.header.alignment_power, |offset| {
+            self.read_record_at(offset)
+        })
+    }
+
+    pub fn read_value(&self, record: &mut Record<U>) -> Result<()> {
+        if record.value.is_read() {
+            return Ok(());
+        }
+
+        record
+            .value
+            .read_value(&mut self.reader.reader_at(0))
+            .map_err(Error::corrupt_record(record.space_offset()))?;
+        if self.header.options.compression() == Compression::External {
+            decode_value(self.codec.as_deref(), record)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>> {
+        let key = self.hash(key.as_ref());
+        match self.get_record(&key)? {
+            None => Ok(None),
+            Some(mut record) => {
+                self.read_value(&mut record)?;
+                let value = record.value.into_value();
+                Ok(Some(value.into_value()))
+            }
+        }
+    }
+
+    pub fn get_detail<'a, K: AsRef<[u8]> + ?Sized>(
+        &self,
+        key: &'a K,
+    ) -> Result<(KeyWithHash<'a>, bool, Vec<Record<U>>)> {
+        let key = self.hash(key.as_ref());
+        let (found, visited_records) = self.get_record_detail(&key)?;
+        Ok((key, found, visited_records))
+    }
+
+    pub fn dump_bucket(&self, bucket_number: u64) -> Result<Vec<Record<U>>> {
+        let rec_off = self.read_bucket(bucket_number)?;
+        tree::traverse(rec_off, self.header.alignment_power, |offset| {
+            self.read_record_at(offset)
+        })
+    }
+}
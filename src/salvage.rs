@@ -0,0 +1,153 @@
+use std::{
+    io::{Read, Seek, SeekFrom},
+    marker::PhantomData,
+};
+
+use binrw::{BinReaderExt, Endian};
+
+use crate::{
+    binrw_types::{Record, RecordSpace, U32orU64},
+    compression::{Compression, ValueCodec},
+    decode_value, hash_value, Error, Result, TCHDB,
+};
+
+/// A piece of a database found by `TCHDB::salvage`
+#[derive(Debug)]
+pub enum Salvaged<U: U32orU64> {
+    /// A valid record, whose value has been read
+    Record(Record<U>),
+    /// A range of `size` bytes from `offset`, where no valid record space is found
+    Skipped { offset: u64, size: u64 },
+}
+
+impl<U: U32orU64, R: Read + Seek> TCHDB<U, R> {
+    /// Read all records like `read_record_spaces(true)`, but skip corrupt regions instead of
+    /// stopping there. Corrupt regions are skipped by the alignment until a plausible record
+    /// or free block is found, and reported as `Salvaged::Skipped`.
+    /// Records are validated by their hash values and sizes, and by decompressing values
+    /// if the database is compressed. Broken values of uncompressed databases are not detected.
+    pub fn salvage(&mut self) -> Result<SalvageIter<'_, U, R>> {
+        // the file may be longer or shorter than the header says if a writer crashed
+        let file_size = self.reader.seek(SeekFrom::End(0))?;
+        // the header may be corrupt too
+        let alignment = 1u64
+            .checked_shl(self.header.alignment_power as u32)
+            .unwrap_or(1);
+
+        Ok(SalvageIter {
+            reader: &mut self.reader,
+            codec: self.codec.as_deref(),
+            endian: self.endian,
+            compression: self.header.options.compression(),
+            alignment,
+            file_size,
+            next_pos: self.header.first_record,
+            skipped_from: None,
+            pending: None,
+            bucket_type: PhantomData,
+        })
+    }
+}
+
+pub struct SalvageIter<'a, U: U32orU64, R> {
+    reader: &'a mut R,
+    codec: Option<&'a dyn ValueCodec>,
+    endian: Endian,
+    compression: Compression,
+    alignment: u64,
+    file_size: u64,
+    next_pos: u64,
+    // the start of the corrupt region being skipped
+    skipped_from: Option<u64>,
+    // the record found just after a corrupt region
+    pending: Option<Record<U>>,
+    bucket_type: PhantomData<fn() -> U>,
+}
+
+impl<'a, U: U32orU64, R: Read + Seek> Iterator for SalvageIter<'a, U, R> {
+    type Item = Result<Salvaged<U>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(record) = self.pending.take() {
+            return Some(Ok(Salvaged::Record(record)));
+        }
+
+        while self.next_pos < self.file_size {
+            let pos = self.next_pos;
+            let (record, size) = match self.read_at(pos) {
+                Ok(Some(found)) => found,
+                Ok(None) => {
+                    self.skipped_from.get_or_insert(pos);
+                    self.next_pos = (pos / self.alignment + 1) * self.alignment;
+                    continue;
+                }
+                Err(e) => {
+                    self.next_pos = self.file_size;
+                    return Some(Err(e));
+                }
+            };
+            self.next_pos = pos + size;
+
+            match (self.skipped_from.take(), record) {
+                (Some(offset), record) => {
+                    self.pending = record;
+                    return Some(Ok(Salvaged::Skipped {
+                        offset,
+                        size: pos - offset,
+                    }));
+                }
+                (None, Some(record)) => return Some(Ok(Salvaged::Record(record))),
+                (None, None) => continue,
+            }
+        }
+
+        self.skipped_from.take().map(|offset| {
+            Ok(Salvaged::Skipped {
+                offset,
+                size: self.file_size - offset,
+            })
+        })
+    }
+}
+
+impl<'a, U: U32orU64, R: Read + Seek> SalvageIter<'a, U, R> {
+    /// Read a record or a free block at `pos` and return it with its size,
+    /// or `None` if it is not plausible
+    fn read_at(&mut self, pos: u64) -> Result<Option<(Option<Record<U>>, u64)>> {
+        self.reader.seek(SeekFrom::Start(pos))?;
+        let record_space = self
+            .reader
+            .read_type_args(self.endian, (pos, false, self.compression));
+
+        match record_space {
+            Ok(RecordSpace::Record(mut record)) => {
+                if record.hash_value != hash_value(&record.key)
+                    || record.next_record() > self.file_size
+                {
+                    return Ok(None);
+                }
+                if record.value.read_value(self.reader).is_err() {
+                    return Ok(None);
+                }
+                if self.compression == Compression::External {
+                    match decode_value(self.codec, &mut record) {
+                        Ok(()) => {}
+                        Err(Error::Codec { .. }) => return Ok(None),
+                        Err(e) => return Err(e),
+                    }
+                }
+
+                let size = record.space_size();
+                Ok(Some((Some(record), size)))
+            }
+            Ok(RecordSpace::FreeBlock(free_block)) => {
+                let size = free_block.block_size as u64;
+                if !size.is_multiple_of(self.alignment) || pos + size > self.file_size {
+                    return Ok(None);
+                }
+                Ok(Some((None, size)))
+            }
+            Err(_) => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    panic,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
+};
+
+use crate::{
+    binrw_types::{RecordSpace, U32orU64},
+    RecordSpaceIter, Result, TCHDBConcurrent,
+};
+
+/// The number of chunks assigned to each thread, to balance the load
+const CHUNKS_PER_JOB: u64 = 4;
+/// Records are read sequentially, so a large buffer saves reads
+const BUFFER_SIZE: usize = 64 * 1024;
+
+impl<U: U32orU64> TCHDBConcurrent<U> {
+    /// Read all record spaces like `read_record_spaces`, with `jobs` threads.
+    ///
+    /// The region of records is split into chunks which start at the roots of the bucket
+    /// trees, as they are known to be boundaries of records. Each thread calls `f` with the
+    /// state created by `init` in the order of the file within a chunk, but chunks are
+    /// scanned in no particular order. The states of all threads are returned.
+    /// The scan stops at the first error returned by `f` or by reading the file.
+    pub fn scan_parallel<S, I, F>(&self, jobs: usize, pv: bool, init: I, f: F) -> Result<Vec<S>>
+    where
+        S: Send,
+        I: Fn() -> S + Sync,
+        F: Fn(&mut S, RecordSpace<U>) -> Result<()> + Sync,
+    {
+        let jobs = jobs.max(1);
+        let boundaries = self.chunk_boundaries(jobs as u64 * CHUNKS_PER_JOB)?;
+        let next_chunk = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+
+        let scan = || -> Result<S> {
+            let mut state = init();
+            let mut reader = self
+                .reader
+                .reader_with_capacity(self.header.first_record, BUFFER_SIZE);
+            loop {
+                let i = next_chunk.fetch_add(1, Ordering::Relaxed);
+                if i + 1 >= boundaries.len() || failed.load(Ordering::Relaxed) {
+                    return Ok(state);
+                }
+
+                let records = RecordSpaceIter::new(
+                    &mut reader,
+                    pv,
+                    self.endian,
+                    &self.header,
+                    self.codec.as_deref(),
+                )
+                .range(boundaries[i], boundaries[i + 1]);
+                for record in records {
+                    if let Err(e) = record.and_then(|record| f(&mut state, record)) {
+                        failed.store(true, Ordering::Relaxed);
+                        return Err(e);
+                    }
+                }
+            }
+        };
+
+        thread::scope(|s| {
+            let handles: Vec<_> = (0..jobs).map(|_| s.spawn(scan)).collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+                .collect()
+        })
+    }
+
+    /// Offsets which split the region of records into at most `chunk_number` chunks,
+    /// including the first record and the end of the file
+    fn chunk_boundaries(&self, chunk_number: u64) -> Result<Vec<u64>> {
+        let first_record = self.header.first_record;
+        let file_size = self.header.file_size;
+
+        let mut roots: Vec<u64> = self
+            .read_buckets()?
+            .0
+            .into_iter()
+            .filter(|rec_off| !rec_off.is_empty())
+            .map(|rec_off| rec_off.offset(self.header.alignment_power))
+            .filter(|&offset| first_record < offset && offset < file_size)
+            .collect();
+        roots.sort_unstable();
+
+        let chunk_size = (file_size.saturating_sub(first_record) / chunk_number).max(1);
+        let mut boundaries = vec![first_record];
+        for root in roots {
+            if root >= boundaries[boundaries.len() - 1] + chunk_size {
+                boundaries.push(root);
+            }
+        }
+        boundaries.push(file_size.max(first_record));
+
+        Ok(boundaries)
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use binrw::{io::BufReader, BinReaderExt, BinWriterExt};
+
+use crate::{
+    binrw_types::{U32orU64, WalEntry},
+    wal, Error, Result, TCHDB,
+};
+
+/// The state of a transaction, whose original regions are logged before being modified
+#[derive(Debug)]
+pub(crate) struct Transaction {
+    wal: File,
+    wal_path: PathBuf,
+    /// The file size at the beginning, regions after which needn't be logged
+    end: u64,
+    /// Regions already logged by their offsets and sizes
+    logged: HashSet<(u64, u64)>,
+}
+
+impl<U: U32orU64, R: Read + Seek> TCHDB<U, R> {
+    /// Log the original bytes of the region before modifying it in a transaction
+    pub(crate) fn log_region(&mut self, offset: u64, size: u64) -> Result<()> {
+        let Some(tran) = &mut self.tran else {
+            return Ok(());
+        };
+        if offset >= tran.end || !tran.logged.insert((offset, size)) {
+            return Ok(());
+        }
+
+        let size = size.min(tran.end - offset);
+        let mut data = vec![0; size as usize];
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.reader.read_exact(&mut data)?;
+        tran.wal
+            .write_type(&WalEntry { offset, data }, self.endian)
+            .map_err(Error::write_failed)?;
+        // the log must be durable before the region is overwritten
+        tran.wal.sync_data()?;
+        Ok(())
+    }
+}
+
+/// Transactions compatible with tokyo cabinet, which logs regions into `<path>.wal` before
+/// modifying them. If the process crashes during a transaction, both this crate and
+/// tokyo cabinet can roll the database back by the log.
+impl<U: U32orU64> TCHDB<U, File> {
+    /// Begin a transaction, whose log is `wal::wal_path` of the path the database was opened
+    /// with. `HDBFOPEN` is set until the transaction ends, as tokyo cabinet sets it while
+    /// writing.
+    pub fn tran_begin(&mut self) -> Result<()> {
+        if self.tran.is_some() {
+            return Err(Error::NestedTransaction);
+        }
+
+        let wal_path = wal::wal_path(self.path.as_ref().ok_or(Error::UnknownPath)?);
+        let mut wal = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&wal_path)?;
+        wal.write_type(&self.header.file_size, self.endian)
+            .map_err(Error::write_failed)?;
+        self.tran = Some(Transaction {
+            wal,
+            wal_path,
+            end: self.header.file_size,
+            logged: HashSet::new(),
+        });
+
+        // the header is always modified, and logged before `HDBFOPEN` is set so that it is
+        // restored clean
+        self.log_region(0, 256)?;
+        self.header.additional_flags.open = true;
+        self.write_header()?;
+        self.reader.sync_data()?;
+        Ok(())
+    }
+
+    /// Make the modifications durable and remove the log
+    pub fn tran_commit(&mut self) -> Result<()> {
+        let tran = self.tran.take().ok_or(Error::NoTransaction)?;
+        self.reader.sync_data()?;
+        self.finish(tran)
+    }
+
+    /// Restore the database to the beginning of the transaction by the log
+    pub fn tran_abort(&mut self) -> Result<()> {
+        let mut tran = self.tran.take().ok_or(Error::NoTransaction)?;
+        tran.wal.flush()?;
+        let log = wal::read(BufReader::new(&tran.wal), self.endian)?;
+
+        // the oldest image of each region is written at last
+        for entry in log.entries.iter().rev() {
+            self.reader.seek(SeekFrom::Start(entry.offset))?;
+            self.reader.write_all(&entry.data)?;
+        }
+        self.reader.set_len(log.file_size)?;
+        self.reader.sync_data()?;
+
+        self.reader.seek(SeekFrom::Start(0))?;
+        self.header = self
+            .reader
+            .read_type(self.endian)
+            .map_err(Error::CorruptHeader)?;
+        self.finish(tran)
+    }
+
+    /// Empty the log, then clear `HDBFOPEN` and remove the log. The database must be durable
+    /// before, as emptying the log is the point after which nothing is rolled back, like
+    /// `tchdbtrancommit` truncating the log.
+    fn finish(&mut self, tran: Transaction) -> Result<()> {
+        tran.wal.set_len(0)?;
+        tran.wal.sync_data()?;
+
+        self.header.additional_flags.open = false;
+        self.write_header()?;
+        self.reader.sync_data()?;
+
+        drop(tran.wal);
+        fs::remove_file(&tran.wal_path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use std::{cmp::Ordering, collections::HashSet};
+
+use crate::{
+    binrw_types::{Record, RecordOffset, RecordSpace, U32orU64},
+    compare_record, Error, KeyWithHash, Result,
+};
+
+/// Following chains from a bucket to the record of a key, shared by all readers.
+/// The record at each offset given by `next_offset` is read and passed to `visit`, so that
+/// async readers can read them too. Sync readers use `search` instead.
+pub(crate) struct KeySearch<'a, 'k, U: U32orU64> {
+    key: &'a KeyWithHash<'k>,
+    alignment_power: u8,
+    next: RecordOffset<U>,
+    visited: VisitedOffsets,
+    records: Vec<Record<U>>,
+}
+
+impl<'a, 'k, U: U32orU64> KeySearch<'a, 'k, U> {
+    pub(crate) fn new(
+        key: &'a KeyWithHash<'k>,
+        bucket: RecordOffset<U>,
+        alignment_power: u8,
+    ) -> Self {
+        KeySearch {
+            key,
+            alignment_power,
+            next: bucket,
+            visited: VisitedOffsets::default(),
+            records: Vec::new(),
+        }
+    }
+
+    /// The offset of the record to read next, or `None` if the chain ends
+    pub(crate) fn next_offset(&mut self) -> Result<Option<u64>> {
+        if self.next.is_empty() {
+            return Ok(None);
+        }
+        let offset = self.next.offset(self.alignment_power);
+        self.visited.visit(offset)?;
+        Ok(Some(offset))
+    }
+
+    /// Follow the chains of the record read at the last offset, returns true if it has the key
+    pub(crate) fn visit(&mut self, record: Record<U>) -> bool {
+        let ordering = compare_record(self.key, &record);
+        self.next = match ordering {
+            Ordering::Greater => record.left_chain,
+            Ordering::Less => record.right_chain,
+            Ordering::Equal => RecordOffset::empty(),
+        };
+        self.records.push(record);
+        ordering.is_eq()
+    }
+
+    /// The visited records, ending with the record of the key if found
+    pub(crate) fn into_records(self) -> Vec<Record<U>> {
+        self.records
+    }
+}
+
+/// Find the record of `key` from `bucket` as `get_record_detail` does,
+/// reading records with `read_record_at`
+pub(crate) fn search<U: U32orU64>(
+    key: &KeyWithHash,
+    bucket: RecordOffset<U>,
+    alignment_power: u8,
+    mut read_record_at: impl FnMut(u64) -> Result<Record<U>>,
+) -> Result<(bool, Vec<Record<U>>)> {
+    let mut search = KeySearch::new(key, bucket, alignment_power);
+    while let Some(offset) = search.next_offset()? {
+        if search.visit(read_record_at(offset)?) {
+            return Ok((true, search.into_records()));
+        }
+    }
+    Ok((false, search.into_records()))
+}
+
+/// Collect records in the tree from `root` from the greatest, without recursion for
+/// degenerate trees
+pub(crate) fn traverse<U: U32orU64>(
+    mut rec_off: RecordOffset<U>,
+    alignment_power: u8,
+    mut read_record_at: impl FnMut(u64) -> Result<Record<U>>,
+) -> Result<Vec<Record<U>>> {
+    let mut visited = VisitedOffsets::default();
+    let mut ancestors = Vec::new();
+    let mut records = Vec::new();
+    loop {
+        while !rec_off.is_empty() {
+            let offset = rec_off.offset(alignment_power);
+            visited.visit(offset)?;
+            let record = read_record_at(offset)?;
+            rec_off = record.right_chain;
+            ancestors.push(record);
+        }
+
+        let Some(record) = ancestors.pop() else {
+            return Ok(records);
+        };
+        rec_off = record.left_chain;
+        records.push(record);
+    }
+}
+
+/// The record space read at `offset` in chains, where free blocks must not be linked
+pub(crate) fn expect_record<U: U32orU64>(offset: u64, space: RecordSpace<U>) -> Result<Record<U>> {
+    match space {
+        RecordSpace::Record(record) => Ok(record),
+        RecordSpace::FreeBlock(_) => Err(Error::UnexpectedFreeBlock { offset }),
+    }
+}
+
+/// Offsets of records visited while following chains, which may have cycles in corrupt files
+#[derive(Default)]
+pub(crate) struct VisitedOffsets(HashSet<u64>);
+
+impl VisitedOffsets {
+    pub(crate) fn visit(&mut self, offset: u64) -> Result<()> {
+        if self.0.insert(offset) {
+            Ok(())
+        } else {
+            Err(Error::ChainCycle { offset })
+        }
+    }
+}
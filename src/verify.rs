@@ -0,0 +1,399 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    io::{Read, Seek, SeekFrom},
+    mem,
+    rc::Rc,
+};
+
+use crate::{
+    binrw_types::{Record, RecordOffset, RecordSpace, U32orU64},
+    compare_keys, Result, TCHDB,
+};
+
+/// A discrepancy found by `TCHDB::verify`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finding {
+    /// The sequential scan failed, records after `offset` are not checked
+    CorruptRecordSpace { offset: u64, reason: String },
+    /// A chain at `chain` points to `target`, which is not the start of a record
+    BrokenChain { chain: u64, target: u64 },
+    /// The record is linked from more than one chain
+    MultiplyLinkedRecord { offset: u64, chain: u64 },
+    /// The record is not reachable from any bucket
+    UnreachableRecord { offset: u64 },
+    /// The record is reachable from `bucket`, but its key belongs to `expected`
+    WrongBucket {
+        offset: u64,
+        bucket: u64,
+        expected: u64,
+    },
+    /// The hash value stored in the record differs from the one of its key
+    WrongHash {
+        offset: u64,
+        stored: u8,
+        expected: u8,
+    },
+    /// The record is placed at the wrong side of an ancestor in the tree
+    OrderViolation { offset: u64, ancestor: u64 },
+    /// The key of the record is the same as the one of the record at `first`
+    DuplicateKey { offset: u64, first: u64 },
+    /// The number of records in the header differs from the actual one
+    RecordNumberMismatch { header: u64, actual: u64 },
+    /// The file size in the header differs from the actual one
+    FileSizeMismatch { header: u64, actual: u64 },
+    /// The free block is not in the free block pool, although the pool is not full
+    FreeBlockNotInPool { offset: u64, size: u64 },
+    /// The free block pool has an element which doesn't point to a free block of the size
+    InvalidPoolElement { offset: u64, size: u64 },
+}
+
+impl Finding {
+    /// A short name of the kind of the finding
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Finding::CorruptRecordSpace { .. } => "corrupt-record-space",
+            Finding::BrokenChain { .. } => "broken-chain",
+            Finding::MultiplyLinkedRecord { .. } => "multiply-linked-record",
+            Finding::UnreachableRecord { .. } => "unreachable-record",
+            Finding::WrongBucket { .. } => "wrong-bucket",
+            Finding::WrongHash { .. } => "wrong-hash",
+            Finding::OrderViolation { .. } => "order-violation",
+            Finding::DuplicateKey { .. } => "duplicate-key",
+            Finding::RecordNumberMismatch { .. } => "record-number-mismatch",
+            Finding::FileSizeMismatch { .. } => "file-size-mismatch",
+            Finding::FreeBlockNotInPool { .. } => "free-block-not-in-pool",
+            Finding::InvalidPoolElement { .. } => "invalid-pool-element",
+        }
+    }
+
+    /// The offset in the file where the discrepancy is, if any
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            Finding::CorruptRecordSpace { offset, .. }
+            | Finding::MultiplyLinkedRecord { offset, .. }
+            | Finding::UnreachableRecord { offset }
+            | Finding::WrongBucket { offset, .. }
+            | Finding::WrongHash { offset, .. }
+            | Finding::OrderViolation { offset, .. }
+            | Finding::DuplicateKey { offset, .. }
+            | Finding::FreeBlockNotInPool { offset, .. }
+            | Finding::InvalidPoolElement { offset, .. } => Some(*offset),
+            Finding::BrokenChain { chain, .. } => Some(*chain),
+            Finding::RecordNumberMismatch { .. } | Finding::FileSizeMismatch { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // the reason is the error, which already has the offset
+            Finding::CorruptRecordSpace { reason, .. } => f.write_str(reason),
+            Finding::BrokenChain { chain, target } => write!(
+                f,
+                "chain at {:#x} points to {:#x}, which is not a record",
+                chain, target
+            ),
+            Finding::MultiplyLinkedRecord { offset, chain } => write!(
+                f,
+                "record at {:#x} is linked again from the chain at {:#x}",
+                offset, chain
+            ),
+            Finding::UnreachableRecord { offset } => {
+                write!(
+                    f,
+                    "record at {:#x} is not reachable from any bucket",
+                    offset
+                )
+            }
+            Finding::WrongBucket {
+                offset,
+                bucket,
+                expected,
+            } => write!(
+                f,
+                "record at {:#x} is in bucket {}, but should be in bucket {}",
+                offset, bucket, expected
+            ),
+            Finding::WrongHash {
+                offset,
+                stored,
+                expected,
+            } => write!(
+                f,
+                "record at {:#x} has hash {}, but its key has hash {}",
+                offset, stored, expected
+            ),
+            Finding::OrderViolation { offset, ancestor } => write!(
+                f,
+                "record at {:#x} is on the wrong side of the record at {:#x}",
+                offset, ancestor
+            ),
+            Finding::DuplicateKey { offset, first } => write!(
+                f,
+                "record at {:#x} has the same key as the record at {:#x}",
+                offset, first
+            ),
+            Finding::RecordNumberMismatch { header, actual } => write!(
+                f,
+                "header says {} records, but {} records are found",
+                header, actual
+            ),
+            Finding::FileSizeMismatch { header, actual } => write!(
+                f,
+                "header says the file size is {}, but it is {}",
+                header, actual
+            ),
+            Finding::FreeBlockNotInPool { offset, size } => write!(
+                f,
+                "free block at {:#x} (size {}) is not in the free block pool",
+                offset, size
+            ),
+            Finding::InvalidPoolElement { offset, size } => write!(
+                f,
+                "free block pool has an element at {:#x} (size {}), which is not a free block",
+                offset, size
+            ),
+        }
+    }
+}
+
+/// A record to visit in a bucket tree, with the ancestors bounding its key
+struct Visit<U: U32orU64> {
+    chain: u64,
+    rec_off: RecordOffset<U>,
+    // the nearest ancestors whose keys are less and greater than the record
+    lower: Option<Rc<Record<U>>>,
+    upper: Option<Rc<Record<U>>>,
+}
+
+impl<U: U32orU64, R: Read + Seek> TCHDB<U, R> {
+    /// Check the consistency of the whole file, and return discrepancies found
+    pub fn verify(&mut self) -> Result<Vec<Finding>> {
+        let mut findings = Vec::new();
+
+        let (records, free_blocks, stop) = self.scan(&mut findings)?;
+        let complete = stop.is_none();
+
+        if complete {
+            let actual = records.len() as u64;
+            if self.header.record_number != actual {
+                findings.push(Finding::RecordNumberMismatch {
+                    header: self.header.record_number,
+                    actual,
+                });
+            }
+        }
+        let actual = self.reader.seek(SeekFrom::End(0))?;
+        if self.header.file_size != actual {
+            findings.push(Finding::FileSizeMismatch {
+                header: self.header.file_size,
+                actual,
+            });
+        }
+
+        let mut first_records = HashMap::new();
+        for (&offset, key) in &records {
+            if let Some(&first) = first_records.get(key) {
+                findings.push(Finding::DuplicateKey { offset, first });
+            } else {
+                first_records.insert(key, offset);
+            }
+        }
+
+        let reached = self.verify_buckets(&records, complete, &mut findings)?;
+        for &offset in records.keys().filter(|offset| !reached.contains(offset)) {
+            findings.push(Finding::UnreachableRecord { offset });
+        }
+
+        let pool = self.read_free_block_pool()?.0;
+        for elem in &pool {
+            let valid = match stop {
+                // blocks after a corrupt record space are not scanned, read them directly
+                Some(stop) if elem.offset >= stop => matches!(
+                    self.read_record_space_at(elem.offset, false),
+                    Ok(RecordSpace::FreeBlock(block)) if block.block_size as u64 == elem.size
+                ),
+                _ => free_blocks.get(&elem.offset) == Some(&elem.size),
+            };
+            if !valid {
+                findings.push(Finding::InvalidPoolElement {
+                    offset: elem.offset,
+                    size: elem.size,
+                });
+            }
+        }
+        // tokyo cabinet forgets free blocks when the pool is full
+        if pool.len() < self.free_block_pool_capacity() {
+            let in_pool: HashSet<_> = pool.iter().map(|elem| elem.offset).collect();
+            for (offset, size) in free_blocks {
+                if !in_pool.contains(&offset) {
+                    findings.push(Finding::FreeBlockNotInPool { offset, size });
+                }
+            }
+        }
+
+        Ok(findings)
+    }
+
+    /// Read all record spaces sequentially, and return keys of records and sizes of free blocks
+    /// by their offsets, with the offset where the scan stopped at a corrupt record space.
+    #[allow(clippy::type_complexity)]
+    fn scan(
+        &mut self,
+        findings: &mut Vec<Finding>,
+    ) -> Result<(BTreeMap<u64, Vec<u8>>, BTreeMap<u64, u64>, Option<u64>)> {
+        let mut records = BTreeMap::new();
+        let mut free_blocks = BTreeMap::new();
+
+        let mut pos = self.header.first_record;
+        for record_space in self.read_record_spaces(false) {
+            match record_space {
+                Ok(RecordSpace::Record(record)) => {
+                    pos = record.next_record();
+                    records.insert(record.space_offset(), record.key);
+                }
+                Ok(RecordSpace::FreeBlock(free_block)) => {
+                    let size = free_block.block_size as u64;
+                    free_blocks.insert(pos, size);
+                    pos += size;
+                }
+                Err(e) => {
+                    findings.push(Finding::CorruptRecordSpace {
+                        offset: e.offset().unwrap_or(pos),
+                        reason: e.to_string(),
+                    });
+                    return Ok((records, free_blocks, Some(pos)));
+                }
+            }
+        }
+
+        Ok((records, free_blocks, None))
+    }
+
+    /// Walk all bucket trees, and return the offsets of reached records
+    fn verify_buckets(
+        &mut self,
+        records: &BTreeMap<u64, Vec<u8>>,
+        complete: bool,
+        findings: &mut Vec<Finding>,
+    ) -> Result<HashSet<u64>> {
+        let size = mem::size_of::<U>() as u64;
+        let buckets = self.read_buckets()?;
+
+        let mut reached = HashSet::new();
+        for (idx, rec_off) in buckets.0.into_iter().enumerate() {
+            let root = Visit {
+                chain: self.bucket_offset + size * idx as u64,
+                rec_off,
+                lower: None,
+                upper: None,
+            };
+            self.verify_tree(idx as u64, root, records, complete, &mut reached, findings)?;
+        }
+
+        Ok(reached)
+    }
+
+    fn verify_tree(
+        &mut self,
+        bucket: u64,
+        root: Visit<U>,
+        records: &BTreeMap<u64, Vec<u8>>,
+        complete: bool,
+        reached: &mut HashSet<u64>,
+        findings: &mut Vec<Finding>,
+    ) -> Result<()> {
+        let mut stack = vec![root];
+        while let Some(visit) = stack.pop() {
+            if visit.rec_off.is_empty() {
+                continue;
+            }
+
+            let offset = visit.rec_off.offset(self.header.alignment_power);
+            let broken = Finding::BrokenChain {
+                chain: visit.chain,
+                target: offset,
+            };
+            // records after a corrupt record space are unknown, but may be readable
+            if complete && !records.contains_key(&offset) {
+                findings.push(broken);
+                continue;
+            }
+            if !reached.insert(offset) {
+                // don't follow the chains again, they may form a cycle
+                findings.push(Finding::MultiplyLinkedRecord {
+                    offset,
+                    chain: visit.chain,
+                });
+                continue;
+            }
+
+            let record = match self.read_record_space(visit.rec_off, false) {
+                Ok(RecordSpace::Record(record)) => record,
+                Ok(RecordSpace::FreeBlock(_)) | Err(_) => {
+                    findings.push(broken);
+                    continue;
+                }
+            };
+
+            let key = self.hash(&record.key);
+            if key.idx != bucket {
+                findings.push(Finding::WrongBucket {
+                    offset,
+                    bucket,
+                    expected: key.idx,
+                });
+            }
+            if key.hash != record.hash_value {
+                findings.push(Finding::WrongHash {
+                    offset,
+                    stored: record.hash_value,
+                    expected: key.hash,
+                });
+            }
+            let bounds = [
+                (&visit.lower, Ordering::Greater),
+                (&visit.upper, Ordering::Less),
+            ];
+            for (ancestor, expected) in bounds {
+                match ancestor {
+                    Some(ancestor) if compare_linked(&record, ancestor) != expected => {
+                        findings.push(Finding::OrderViolation {
+                            offset,
+                            ancestor: ancestor.space_offset(),
+                        });
+                    }
+                    _ => {}
+                }
+            }
+
+            let size = mem::size_of::<U>() as u64;
+            let record = Rc::new(record);
+            // visit the left subtree first, where greater keys are linked
+            stack.push(Visit {
+                chain: record.offset + 1 + size,
+                rec_off: record.right_chain,
+                lower: visit.lower,
+                upper: Some(Rc::clone(&record)),
+            });
+            stack.push(Visit {
+                chain: record.offset + 1,
+                rec_off: record.left_chain,
+                lower: Some(Rc::clone(&record)),
+                upper: visit.upper,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Compare records in the order of the binary trees, by their stored hash values
+fn compare_linked<U: U32orU64>(a: &Record<U>, b: &Record<U>) -> Ordering {
+    a.hash_value
+        .cmp(&b.hash_value)
+        .then_with(|| compare_keys(&a.key, &b.key))
+}
@@ -0,0 +1,247 @@
+//! The write-ahead log `<path>.wal` of tokyo cabinet transactions.
+//!
+//! The log has the file size at the beginning of the transaction, followed by the original
+//! bytes of each region before it was modified, starting with the header. It is truncated
+//! when the transaction is committed, so a log with entries means an interrupted transaction,
+//! whose state before the transaction is restored by writing the entries in reverse order and
+//! truncating the file.
+
+use std::{
+    cmp,
+    collections::BTreeMap,
+    ffi::OsString,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use binrw::{io::BufReader, BinReaderExt, Endian};
+
+use crate::{binrw_types::WalEntry, Error, Result};
+
+/// The file size and the header, which tokyo cabinet requires to restore the log
+const MIN_WAL_SIZE: u64 = 8 + 256;
+
+/// The offset of `Header::additional_flags`
+const ADDITIONAL_FLAGS_OFFSET: usize = 33;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wal {
+    /// The size of the database file before the transaction
+    pub file_size: u64,
+    pub entries: Vec<WalEntry>,
+    /// The last entry was being written, which is ignored as its region wasn't modified yet
+    pub truncated: bool,
+}
+
+/// The path of the log of the database at `path`
+pub fn wal_path<T: AsRef<Path>>(path: T) -> PathBuf {
+    let mut wal_path = OsString::from(path.as_ref());
+    wal_path.push(".wal");
+    PathBuf::from(wal_path)
+}
+
+/// Whether the database at `path` has the log of an interrupted transaction
+pub fn is_pending<T: AsRef<Path>>(path: T) -> Result<bool> {
+    match wal_path(path).metadata() {
+        Ok(metadata) => Ok(metadata.len() >= MIN_WAL_SIZE),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// An async version of `is_pending`
+#[cfg(feature = "async")]
+pub(crate) async fn is_pending_async<T: AsRef<Path>>(path: T) -> Result<bool> {
+    match tokio::fs::metadata(wal_path(path)).await {
+        Ok(metadata) => Ok(metadata.len() >= MIN_WAL_SIZE),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Read the log of the database at `path`, returns `None` if there is no interrupted
+/// transaction
+pub fn open<T: AsRef<Path>>(path: T) -> Result<Option<Wal>> {
+    if !is_pending(&path)? {
+        return Ok(None);
+    }
+
+    let mut reader = BufReader::new(File::open(wal_path(path))?);
+    let endian = detect_endian(&mut reader)?;
+    read(reader, endian).map(Some)
+}
+
+/// Detect the endian by the first entry, which is the header of 256 bytes
+pub fn detect_endian<R: Read + Seek>(reader: &mut R) -> Result<Endian> {
+    // skip the file size and the offset
+    reader.seek(SeekFrom::Start(16))?;
+    let size: u32 = reader.read_le().map_err(Error::CorruptWal)?;
+    Ok(if size == 256 {
+        Endian::Little
+    } else {
+        Endian::Big
+    })
+}
+
+pub fn read<R: Read + Seek>(mut reader: R, endian: Endian) -> Result<Wal> {
+    reader.seek(SeekFrom::Start(0))?;
+    let file_size = reader.read_type(endian).map_err(Error::CorruptWal)?;
+
+    let mut entries = Vec::new();
+    let mut truncated = false;
+    loop {
+        let pos = reader.stream_position()?;
+        if reader.read(&mut [0])? == 0 {
+            break;
+        }
+        reader.seek(SeekFrom::Start(pos))?;
+
+        match reader.read_type(endian) {
+            Ok(entry) => entries.push(entry),
+            Err(e) if e.is_eof() => {
+                truncated = true;
+                break;
+            }
+            Err(e) => return Err(Error::CorruptWal(e)),
+        }
+    }
+
+    Ok(Wal {
+        file_size,
+        entries,
+        truncated,
+    })
+}
+
+/// A view of the database before the interrupted transaction, which doesn't modify the file
+#[derive(Debug)]
+pub struct WalOverlay<R> {
+    inner: R,
+    file_size: u64,
+    /// The original bytes by their offsets, which don't overlap
+    regions: BTreeMap<u64, Vec<u8>>,
+    pos: u64,
+}
+
+impl<R: Seek> WalOverlay<R> {
+    /// Without the log, the view is the same as `inner`
+    pub fn new(mut inner: R, wal: Option<Wal>) -> io::Result<Self> {
+        let Some(wal) = wal else {
+            let file_size = inner.seek(SeekFrom::End(0))?;
+            return Ok(WalOverlay {
+                inner,
+                file_size,
+                regions: BTreeMap::new(),
+                pos: 0,
+            });
+        };
+
+        let mut overlay = WalOverlay {
+            inner,
+            file_size: wal.file_size,
+            regions: BTreeMap::new(),
+            pos: 0,
+        };
+        for entry in wal.entries {
+            overlay.add(entry.offset, &entry.data);
+        }
+        Ok(overlay)
+    }
+
+    /// Add the bytes not covered by former entries, which are older
+    fn add(&mut self, offset: u64, data: &[u8]) {
+        let end = offset + data.len() as u64;
+        let covered: Vec<_> = self
+            .regions
+            .range(..end)
+            .rev()
+            .map(|(&start, region)| (start, start + region.len() as u64))
+            .take_while(|&(_, region_end)| region_end > offset)
+            .collect();
+
+        let mut pos = offset;
+        for (start, region_end) in covered.into_iter().rev() {
+            if pos < start {
+                let piece = &data[(pos - offset) as usize..(start - offset) as usize];
+                self.regions.insert(pos, piece.to_vec());
+            }
+            pos = cmp::max(pos, region_end);
+        }
+        if pos < end {
+            self.regions
+                .insert(pos, data[(pos - offset) as usize..].to_vec());
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read + Seek> Read for WalOverlay<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.file_size {
+            return Ok(0);
+        }
+        let limit = cmp::min(buf.len() as u64, self.file_size - self.pos) as usize;
+
+        let read = match self.regions.range(..=self.pos).next_back() {
+            Some((&start, region)) if self.pos < start + region.len() as u64 => {
+                let region = &region[(self.pos - start) as usize..];
+                let len = cmp::min(limit, region.len());
+                buf[..len].copy_from_slice(&region[..len]);
+                len
+            }
+            _ => {
+                let next = self
+                    .regions
+                    .range(self.pos + 1..)
+                    .next()
+                    .map_or(u64::MAX, |(&start, _)| start);
+                let len = cmp::min(limit as u64, next - self.pos) as usize;
+                self.inner.seek(SeekFrom::Start(self.pos))?;
+                self.inner.read(&mut buf[..len])?
+            }
+        };
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R> Seek for WalOverlay<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(delta) => self.file_size.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        self.pos = pos.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
+
+/// Write the database at `path` before the interrupted transaction into `dst`, as tokyo
+/// cabinet restores it on opening. `HDBFOPEN`, which was set by the interrupted writer, is
+/// cleared as the written database is consistent.
+/// Returns `false` without writing if there is no log.
+pub fn recover<T: AsRef<Path>, W: io::Write>(path: T, dst: &mut W) -> Result<bool> {
+    let Some(wal) = open(&path)? else {
+        return Ok(false);
+    };
+
+    let file = BufReader::new(File::open(path)?);
+    let mut overlay = WalOverlay::new(file, Some(wal))?;
+    let mut header = [0; 256];
+    overlay.read_exact(&mut header)?;
+    header[ADDITIONAL_FLAGS_OFFSET] &= !0x01;
+    dst.write_all(&header)?;
+    io::copy(&mut overlay, dst)?;
+    dst.flush()?;
+    Ok(true)
+}
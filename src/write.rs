@@ -0,0 +1,240 @@
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    mem,
+};
+
+use binrw::BinWriterExt;
+
+use crate::{
+    binrw_types::{
+        FreeBlock, FreeBlockPool, FreeBlockPoolElement, Record, RecordOffset, RecordSpace, U32orU64,
+    },
+    compare_record,
+    compression::Compression,
+    tree::VisitedOffsets,
+    Error, KeyWithHash, Result, TCHDB,
+};
+
+impl<U: U32orU64, R: Read + Write + Seek> TCHDB<U, R> {
+    /// Store a record, overwriting the value if the key already exists
+    pub fn put<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V) -> Result<()> {
+        let key = self.hash(key.as_ref());
+        let value = self.encode_value(value.as_ref())?;
+
+        let (found, mut visited_records) = self.get_record_detail(&key)?;
+        let old_record = if found { visited_records.pop() } else { None };
+        let (left_chain, right_chain) = match &old_record {
+            Some(r) => (r.left_chain, r.right_chain),
+            None => (RecordOffset::empty(), RecordOffset::empty()),
+        };
+
+        if let Some(old_record) = &old_record {
+            let mut record = Record::new(
+                old_record.space_offset(),
+                key.hash,
+                left_chain,
+                right_chain,
+                key.key.to_vec(),
+                value,
+            );
+            let size = record.space_size();
+            let old_size = old_record.space_size();
+            if size <= old_size && old_size - size <= u16::MAX as u64 {
+                // overwrite in place
+                record.padding_size = (old_size - size) as u16;
+                return self.write_record(record);
+            }
+
+            return self.append_record(&key, visited_records.last(), record, Some(old_record));
+        }
+
+        let record = Record::new(
+            0,
+            key.hash,
+            left_chain,
+            right_chain,
+            key.key.to_vec(),
+            value,
+        );
+        self.append_record(&key, visited_records.last(), record, None)
+    }
+
+    /// Remove a record as `tchdbout` does, returns false if the key doesn't exist
+    pub fn out<K: AsRef<[u8]>>(&mut self, key: K) -> Result<bool> {
+        let key = self.hash(key.as_ref());
+
+        let (found, mut visited_records) = self.get_record_detail(&key)?;
+        if !found {
+            return Ok(false);
+        }
+        let record = visited_records.pop().unwrap();
+        let chain_pos = self.chain_position(&key, visited_records.last());
+
+        self.free_space(record.space_offset(), record.space_size())?;
+
+        let child = match (record.left_chain.is_empty(), record.right_chain.is_empty()) {
+            (false, true) => record.left_chain,
+            (true, false) => record.right_chain,
+            (true, true) => RecordOffset::empty(),
+            (false, false) => {
+                // hang the right subtree on the rightmost record of the left subtree
+                let mut rightmost = record.left_chain;
+                let mut visited = VisitedOffsets::default();
+                loop {
+                    let offset = rightmost.offset(self.header.alignment_power);
+                    visited.visit(offset)?;
+                    match self.read_record_space(rightmost, false)? {
+                        RecordSpace::Record(r) if r.right_chain.is_empty() => {
+                            self.write_chain(
+                                r.offset + 1 + mem::size_of::<U>() as u64,
+                                record.right_chain,
+                            )?;
+                            break;
+                        }
+                        RecordSpace::Record(r) => rightmost = r.right_chain,
+                        RecordSpace::FreeBlock(_) => {
+                            return Err(Error::UnexpectedFreeBlock { offset })
+                        }
+                    }
+                }
+                record.left_chain
+            }
+        };
+        self.write_chain(chain_pos, child)?;
+
+        self.header.record_number -= 1;
+        self.write_header()?;
+
+        Ok(true)
+    }
+
+    /// Write a record at the end of the file and link it from the parent
+    fn append_record(
+        &mut self,
+        key: &KeyWithHash,
+        parent: Option<&Record<U>>,
+        mut record: Record<U>,
+        old_record: Option<&Record<U>>,
+    ) -> Result<()> {
+        let offset = self.header.file_size;
+        let rec_off = RecordOffset::<U>::new(offset, self.header.alignment_power)
+            .ok_or(Error::OffsetOverflow { offset })?;
+
+        record.offset = offset + 1;
+        let size = record.space_size();
+        let align = 1u64 << self.header.alignment_power;
+        record.padding_size = ((align - (offset + size) % align) % align) as u16;
+        let size = record.space_size();
+
+        self.write_record(record)?;
+        self.write_chain(self.chain_position(key, parent), rec_off)?;
+
+        match old_record {
+            Some(old_record) => {
+                self.free_space(old_record.space_offset(), old_record.space_size())?
+            }
+            None => self.header.record_number += 1,
+        }
+        self.header.file_size += size;
+        self.write_header()
+    }
+
+    fn encode_value(&self, value: &[u8]) -> Result<Vec<u8>> {
+        let compression = self.header.options.compression();
+        let value = if compression == Compression::External {
+            let codec = self.codec.as_deref().ok_or(Error::MissingCodec)?;
+            codec.encode(value)?
+        } else {
+            compression.compress(value, self.endian)?
+        };
+
+        Ok(value)
+    }
+
+    /// The position of the chain in the parent record (or the bucket) to link `key`
+    fn chain_position(&self, key: &KeyWithHash, parent: Option<&Record<U>>) -> u64 {
+        let size = mem::size_of::<U>() as u64;
+        match parent {
+            None => self.bucket_offset + size * key.idx,
+            // skip the hash value
+            Some(parent) if compare_record(key, parent).is_gt() => parent.offset + 1,
+            Some(parent) => parent.offset + 1 + size,
+        }
+    }
+
+    fn write_chain(&mut self, pos: u64, rec_off: RecordOffset<U>) -> Result<()> {
+        self.log_region(pos, mem::size_of::<U>() as u64)?;
+        self.reader.seek(SeekFrom::Start(pos))?;
+        self.reader
+            .write_type(&rec_off, self.endian)
+            .map_err(Error::write_failed)
+    }
+
+    fn write_record(&mut self, record: Record<U>) -> Result<()> {
+        let offset = record.space_offset();
+        let padding = vec![0; record.padding_size as usize];
+        self.log_region(offset, record.space_size())?;
+
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.reader
+            .write_type(&RecordSpace::Record(record), self.endian)
+            .map_err(Error::write_failed)?;
+        self.reader.write_all(&padding)?;
+
+        Ok(())
+    }
+
+    /// Turn the space into a free block and register it to the free block pool
+    fn free_space(&mut self, offset: u64, size: u64) -> Result<()> {
+        self.write_free_block(offset, size)?;
+
+        let mut pool = self.read_free_block_pool()?.0;
+        let capacity = self.free_block_pool_capacity();
+        if pool.len() >= capacity {
+            // forget the smallest blocks as tokyo cabinet does
+            pool.sort_by_key(|elem| elem.size);
+            pool.drain(..pool.len() + 1 - capacity);
+        }
+        pool.push(FreeBlockPoolElement { offset, size });
+        self.write_free_block_pool(&FreeBlockPool(pool))
+    }
+
+    fn write_free_block_pool(&mut self, pool: &FreeBlockPool) -> Result<()> {
+        let region_size = self.header.first_record - self.free_block_pool_offset;
+        self.log_region(self.free_block_pool_offset, region_size)?;
+        self.reader
+            .seek(SeekFrom::Start(self.free_block_pool_offset))?;
+        self.reader
+            .write_type_args(
+                pool,
+                self.endian,
+                (self.header.alignment_power, region_size),
+            )
+            .map_err(Error::write_failed)
+    }
+
+    fn write_free_block(&mut self, offset: u64, size: u64) -> Result<()> {
+        let free_block = FreeBlock {
+            block_size: size as u32,
+            padding: Vec::new(),
+        };
+        // the magic number and the size
+        self.log_region(offset, 5)?;
+
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.reader
+            .write_type(&RecordSpace::<U>::FreeBlock(free_block), self.endian)
+            .map_err(Error::write_failed)
+    }
+
+    pub(crate) fn write_header(&mut self) -> Result<()> {
+        self.log_region(0, 256)?;
+        self.reader.seek(SeekFrom::Start(0))?;
+        self.reader
+            .write_type(&self.header, self.endian)
+            .map_err(Error::write_failed)?;
+        self.reader.flush()?;
+
+        Ok(())
+    }
+}
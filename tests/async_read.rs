@@ -0,0 +1,127 @@
+#![cfg(feature = "async")]
+
+mod common;
+
+use std::{fs, future::Future, io::Cursor};
+
+use common::{records, small};
+use futures_util::{pin_mut, StreamExt};
+use tchread::{
+    binrw_types::{Record, RecordSpace, U32orU64},
+    load, AsyncReader, Error, Result, TCHDB,
+};
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+/// Keys and values found by the stream of record spaces, or the first error
+async fn stream_records<U, R>(
+    tchdb: &mut TCHDB<U, AsyncReader<R>>,
+) -> Result<Vec<(Vec<u8>, Vec<u8>)>>
+where
+    U: U32orU64,
+    R: tokio::io::AsyncRead + tokio::io::AsyncSeek + Unpin,
+{
+    let stream = tchdb.read_record_spaces(true);
+    pin_mut!(stream);
+    let mut records = Vec::new();
+    while let Some(record_space) = stream.next().await {
+        if let RecordSpace::Record(record) = record_space? {
+            records.push((record.key, record.value.into_value().into_value()));
+        }
+    }
+    Ok(records)
+}
+
+#[test]
+fn get_as_sync_reader() {
+    for path in ["casket.tch", "tests/fixtures/casket-deflate.tch"] {
+        let mut sync = small(load::open(path).unwrap());
+        let expected = records(&mut sync);
+        block_on(async {
+            let mut tchdb = small(load::open_async(path).await.unwrap());
+            for (key, value) in &expected {
+                assert_eq!(
+                    tchdb.get(key).await.unwrap().as_ref(),
+                    Some(value),
+                    "{:?}",
+                    key
+                );
+            }
+            assert_eq!(tchdb.get("missing").await.unwrap(), None);
+        });
+    }
+}
+
+#[test]
+fn get_detail_as_sync_reader() {
+    let mut sync = small(load::open("casket.tch").unwrap());
+    let mut keys: Vec<_> = records(&mut sync).into_keys().collect();
+    keys.push(b"missing".to_vec());
+
+    block_on(async {
+        let mut tchdb = small(load::open_async("casket.tch").await.unwrap());
+        for key in &keys {
+            let (_, found, visited) = tchdb.get_detail(key).await.unwrap();
+            let (_, expected_found, expected) = sync.get_detail(key).unwrap();
+            assert_eq!(found, expected_found, "{:?}", key);
+            let offsets = |records: &[Record<u32>]| -> Vec<u64> {
+                records.iter().map(|record| record.offset).collect()
+            };
+            assert_eq!(offsets(&visited), offsets(&expected), "{:?}", key);
+        }
+    });
+}
+
+#[test]
+fn stream_record_spaces() {
+    let mut sync = small(load::open("casket.tch").unwrap());
+    let expected: Vec<_> = records(&mut sync).into_iter().collect();
+
+    let mut streamed = block_on(async {
+        let mut tchdb = small(load::open_async("casket.tch").await.unwrap());
+        stream_records(&mut tchdb).await.unwrap()
+    });
+    streamed.sort();
+    assert_eq!(streamed, expected);
+}
+
+#[test]
+fn reject_value_size_beyond_file() {
+    // the value size of the record at 0x12c0 is patched to `u32::MAX`
+    let mut data = fs::read("casket.tch").unwrap();
+    data[0x12cd..0x12d2].copy_from_slice(&[0x80, 0x80, 0x80, 0x80, 0x0f]);
+
+    block_on(async {
+        let load = || async {
+            small(
+                load::load_async_with_endian(Cursor::new(data.clone()), binrw::Endian::Little)
+                    .await
+                    .unwrap(),
+            )
+        };
+
+        let mut tchdb = load().await;
+        let error = stream_records(&mut tchdb).await.unwrap_err();
+        assert!(matches!(error, Error::CorruptRecord { offset: 0x12c0, .. }));
+
+        // values read lazily are checked too
+        let mut tchdb = load().await;
+        let mut record = {
+            let stream = tchdb.read_record_spaces(false);
+            pin_mut!(stream);
+            loop {
+                match stream.next().await.unwrap().unwrap() {
+                    RecordSpace::Record(record) if record.space_offset() == 0x12c0 => break record,
+                    _ => {}
+                }
+            }
+        };
+        let error = tchdb.read_value(&mut record).await.unwrap_err();
+        assert!(matches!(error, Error::CorruptRecord { offset: 0x12c0, .. }));
+    });
+}
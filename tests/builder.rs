@@ -0,0 +1,94 @@
+mod common;
+
+use std::{fs, io::Cursor, process::Command};
+
+use common::TempDir;
+use tchread::{binrw_types::Options, Error, TCHDBBuilder};
+
+/// The header except the number of records and the file size, which depend on the records
+fn parameters(data: &[u8]) -> Vec<u8> {
+    [&data[..48], &data[64..256]].concat()
+}
+
+fn build(builder: &TCHDBBuilder) -> Vec<u8> {
+    let mut cursor = Cursor::new(Vec::new());
+    builder.build(&mut cursor).unwrap();
+    cursor.into_inner()
+}
+
+#[test]
+fn same_header_as_tchmgr() {
+    for (fixture, builder) in [
+        ("casket.tch", TCHDBBuilder::new().bucket_number(2)),
+        ("casket-with-free-space.tch", TCHDBBuilder::new()),
+        (
+            "casket-large.tch",
+            TCHDBBuilder::new().options(Options {
+                large: true,
+                ..Options::default()
+            }),
+        ),
+    ] {
+        let expected = fs::read(fixture).unwrap();
+        let data = build(&builder);
+        assert_eq!(parameters(&data), parameters(&expected), "{}", fixture);
+        // the empty bucket array and free block pool up to the first record
+        let first_record = u64::from_le_bytes(expected[64..72].try_into().unwrap());
+        assert_eq!(data.len() as u64, first_record, "{}", fixture);
+        assert!(data[256..].iter().all(|&b| b == 0), "{}", fixture);
+    }
+}
+
+#[test]
+fn round_bucket_number_as_tcgetprime() {
+    for (bucket_number, expected) in [
+        (0, 131071),
+        (1, 1),
+        (2, 2),
+        (4, 5),
+        (1000, 1031),
+        (1031, 1031),
+        (131071, 136607),
+    ] {
+        let data = build(&TCHDBBuilder::new().bucket_number(bucket_number));
+        assert_eq!(
+            u64::from_le_bytes(data[40..48].try_into().unwrap()),
+            expected,
+            "{}",
+            bucket_number
+        );
+    }
+}
+
+#[test]
+fn reject_bucket_array_beyond_small_offsets() {
+    let result = TCHDBBuilder::new()
+        .bucket_number(u64::MAX)
+        .build(Cursor::new(Vec::new()));
+    assert!(matches!(
+        result,
+        Err(Error::BucketNumberOverflow {
+            bucket_number: 4294967291
+        })
+    ));
+}
+
+#[test]
+#[ignore = "needs tchmgr of tokyo cabinet"]
+fn same_header_as_tchmgr_create() {
+    let dir = TempDir::new("builder");
+    for bucket_number in [1, 1000, 100000] {
+        let path = dir.join(format!("created-{}.tch", bucket_number));
+        let status = Command::new("tchmgr")
+            .arg("create")
+            .arg(&path)
+            .arg(bucket_number.to_string())
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let expected = fs::read(&path).unwrap();
+
+        let data = build(&TCHDBBuilder::new().bucket_number(bucket_number));
+        assert_eq!(data, expected, "{}", bucket_number);
+    }
+}
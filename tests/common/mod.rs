@@ -0,0 +1,75 @@
+//! Helpers shared by the integration tests
+#![allow(dead_code)]
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{Read, Seek},
+    path::{Path, PathBuf},
+    process,
+};
+
+use tchread::{
+    binrw_types::{RecordSpace, U32orU64},
+    load::{self, TCHDBLoaded},
+    TCHDB,
+};
+
+/// A directory for copies of fixtures to modify, which is removed on drop
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("tchread-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.0.join(path)
+    }
+
+    /// Copy a fixture into the directory, and return the path of the copy
+    pub fn copy<P: AsRef<Path>>(&self, fixture: P) -> PathBuf {
+        let path = self.join(fixture.as_ref().file_name().unwrap());
+        fs::copy(fixture, &path).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+pub fn small<R>(loaded: TCHDBLoaded<R>) -> TCHDB<u32, R> {
+    match loaded {
+        TCHDBLoaded::Small(tchdb) => tchdb,
+        TCHDBLoaded::Large(_) => panic!("the database is large"),
+    }
+}
+
+/// Keys and values of all records found by the sequential scan
+pub fn records<U: U32orU64, R: Read + Seek>(tchdb: &mut TCHDB<U, R>) -> BTreeMap<Vec<u8>, Vec<u8>> {
+    tchdb
+        .read_record_spaces(true)
+        .filter_map(|record| match record.unwrap() {
+            RecordSpace::Record(record) => {
+                Some((record.key, record.value.into_value().into_value()))
+            }
+            RecordSpace::FreeBlock(_) => None,
+        })
+        .collect()
+}
+
+/// Check that the database at `path` is consistent and has the records
+pub fn check_records(path: &Path, expected: &BTreeMap<Vec<u8>, Vec<u8>>) {
+    let mut tchdb = small(load::open(path).unwrap());
+    assert_eq!(tchdb.verify().unwrap(), vec![]);
+    assert_eq!(tchdb.header.record_number, expected.len() as u64);
+    for (key, value) in expected {
+        assert_eq!(tchdb.get(key).unwrap().as_ref(), Some(value), "{:?}", key);
+    }
+    assert_eq!(&records(&mut tchdb), expected);
+}
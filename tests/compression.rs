@@ -0,0 +1,68 @@
+mod common;
+
+use binrw::Endian;
+use common::small;
+use tchread::{compression::Compression, load};
+
+/// The records written by tests/fixtures/build_fixtures.py and create_with_tchmgr.sh
+fn expected_value(i: usize) -> Vec<u8> {
+    let mut value = format!("value{} ", i).repeat(i % 7 + 1).into_bytes();
+    value.extend((0..(i % 50) as u8).collect::<Vec<_>>());
+    value
+}
+
+fn check_fixture(path: &str) {
+    let mut tchdb = small(load::open(path).unwrap());
+    for i in 0..100 {
+        let value = tchdb.get(format!("key{}", i)).unwrap();
+        assert_eq!(value, Some(expected_value(i)), "key{} of {}", i, path);
+    }
+    assert_eq!(tchdb.get("key100").unwrap(), None);
+}
+
+#[test]
+fn decode_deflate() {
+    check_fixture("tests/fixtures/casket-deflate.tch");
+}
+
+#[test]
+fn decode_bzip2() {
+    check_fixture("tests/fixtures/casket-bzip.tch");
+}
+
+#[test]
+#[ignore = "needs fixtures created by tests/fixtures/create_with_tchmgr.sh"]
+fn decode_deflate_by_tchmgr() {
+    check_fixture("tests/fixtures/casket-tc-d.tch");
+}
+
+#[test]
+#[ignore = "needs fixtures created by tests/fixtures/create_with_tchmgr.sh"]
+fn decode_bzip2_by_tchmgr() {
+    check_fixture("tests/fixtures/casket-tc-b.tch");
+}
+
+#[test]
+#[ignore = "needs fixtures created by tests/fixtures/create_with_tchmgr.sh"]
+fn decode_tcbs_by_tchmgr() {
+    check_fixture("tests/fixtures/casket-tc-t.tch");
+}
+
+#[test]
+fn round_trip_tcbs() {
+    let values = [
+        Vec::new(),
+        b"a".to_vec(),
+        b"banana".to_vec(),
+        expected_value(13),
+        (0..=255u8).cycle().take(10000).collect(),
+        vec![0; 3000],
+    ];
+    for endian in [Endian::Little, Endian::Big] {
+        for value in &values {
+            let data = Compression::Tcbs.compress(value, endian).unwrap();
+            let decoded = Compression::Tcbs.decompress(&data, endian).unwrap();
+            assert_eq!(&decoded, value);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use std::{collections::BTreeMap, fs, path::Path, process::Command};
+
+use common::{check_records, small, TempDir};
+use tchread::{
+    export::{self, DataEncoding, Exporter},
+    import::{self, Importer},
+    load, TCHDBBuilder,
+};
+
+/// Keys and values with separators of text formats, NUL and invalid UTF-8
+fn binary_records() -> BTreeMap<Vec<u8>, Vec<u8>> {
+    [
+        (&b"plain"[..], &b"value"[..]),
+        (b"tab\tkey", b"tab\tvalue"),
+        (b"line\nfeed", b"line\nfeed\r\n"),
+        (b"nul\0", b"\0\0\0"),
+        (b"\xff\xfe", b"\x80 invalid utf-8 \xc3"),
+        ("日本語".as_bytes(), "値".as_bytes()),
+        (b"\"quoted\" \\", b"{\"json\": [1]}"),
+        (b"empty", b""),
+        (b"", b"empty key"),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_vec(), value.to_vec()))
+    .collect()
+}
+
+fn create(path: &Path, records: &BTreeMap<Vec<u8>, Vec<u8>>) {
+    TCHDBBuilder::new().bucket_number(7).create(path).unwrap();
+    let mut tchdb = small(load::open_writable(path).unwrap());
+    for (key, value) in records {
+        tchdb.put(key, value).unwrap();
+    }
+}
+
+#[test]
+fn round_trip_json_lines() {
+    let dir = TempDir::new("export");
+    let src = dir.join("src.tch");
+    let expected = binary_records();
+    create(&src, &expected);
+
+    for encoding in [DataEncoding::Utf8, DataEncoding::Base64, DataEncoding::Hex] {
+        let mut exported = Vec::new();
+        let exporter = Exporter::new(export::Format::JsonLines)
+            .key_encoding(encoding)
+            .value_encoding(encoding);
+        let count = exporter
+            .export(&mut small(load::open(&src).unwrap()), &mut exported)
+            .unwrap();
+        assert_eq!(count, expected.len() as u64);
+
+        let dst = dir.join(format!("{:?}.tch", encoding));
+        create(&dst, &BTreeMap::new());
+        let importer = Importer::new(import::Format::JsonLines)
+            .key_encoding(encoding)
+            .value_encoding(encoding);
+        let mut tchdb = small(load::open_writable(&dst).unwrap());
+        assert_eq!(
+            importer.import(&mut tchdb, &exported[..]).unwrap(),
+            expected.len() as u64
+        );
+        drop(tchdb);
+
+        check_records(&dst, &expected);
+    }
+}
+
+#[test]
+fn round_trip_by_subcommands() {
+    let dir = TempDir::new("export-subcommands");
+    let src = dir.join("src.tch");
+    let expected = binary_records();
+    create(&src, &expected);
+
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_rs-tchread"))
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        output.stdout
+    };
+    let exported = dir.join("exported.jsonl");
+    fs::write(&exported, run(&["export", src.to_str().unwrap()])).unwrap();
+
+    let dst = dir.join("dst.tch");
+    run(&[
+        "import",
+        "--format",
+        "jsonl",
+        dst.to_str().unwrap(),
+        exported.to_str().unwrap(),
+    ]);
+    check_records(&dst, &expected);
+}
@@ -0,0 +1,147 @@
+#!/usr/bin/env python3
+"""Build hash databases without this crate, so that it is tested against independent writers.
+
+- casket-deflate.tch and casket-bzip.tch have values compressed by zlib and libbz2, which
+  tokyo cabinet links for HDBTDEFLATE and HDBTBZIP.
+- casket-order.tch has pairs of keys in the same tree with the same hash value, whose
+  shorter keys are lexicographically greater, ordered by their lengths first as
+  `tcreckeycmp` does.
+
+    python3 tests/fixtures/build_fixtures.py
+"""
+
+import bz2
+import os
+import struct
+import zlib
+
+BUCKET_NUMBER = 1031
+ALIGNMENT_POWER = 4
+FREE_BLOCK_POOL_POWER = 10
+HEADER_SIZE = 256
+
+OPTION_DEFLATE = 0x02
+OPTION_BZIP = 0x04
+
+
+def compressed_records():
+    for i in range(100):
+        key = b"key%d" % i
+        value = (b"value%d " % i) * (i % 7 + 1) + bytes(range(i % 50))
+        yield key, value
+
+
+def raw_deflate(data):
+    compressor = zlib.compressobj(wbits=-15)
+    return compressor.compress(data) + compressor.flush()
+
+
+def bucket_index(key, bucket_number):
+    idx = 19780211
+    for b in key:
+        idx = (idx * 37 + b) % (1 << 64)
+    return idx % bucket_number
+
+
+def hash_value(key):
+    h = 751
+    for b in reversed(key):
+        h = ((h * 31) ^ b) % (1 << 32)
+    return h & 0xFF
+
+
+def order(key):
+    """Greater keys are linked by the left chain"""
+    return (hash_value(key), len(key), key)
+
+
+def vnum(n):
+    out = bytearray()
+    while True:
+        x = n & 0x7F
+        n >>= 7
+        if n == 0:
+            out.append(x)
+            return bytes(out)
+        out.append(0xFF - x)
+
+
+def order_records():
+    """Short keys are linked from the long keys by the right chains"""
+    for short_key, long_key in [(b"z1", b"a0316"), (b"z2", b"a0689"), (b"z3", b"a0596")]:
+        assert hash_value(short_key) == hash_value(long_key)
+        yield long_key, b"value of " + long_key
+        yield short_key, b"value of " + short_key
+
+
+def build(path, records, bucket_number=BUCKET_NUMBER, options=0, compress=lambda value: value):
+    align = 1 << ALIGNMENT_POWER
+    pool_size = 64 + (4 << FREE_BLOCK_POOL_POWER)
+    first_record = HEADER_SIZE + bucket_number * 4 + pool_size
+    first_record = (first_record + align - 1) // align * align
+
+    buckets = [0] * bucket_number
+    # offset -> [key, left, right, encoded value]
+    nodes = {}
+    body = bytearray()
+
+    def link(parent_chain, offset):
+        kind, at = parent_chain
+        if kind == "bucket":
+            buckets[at] = offset >> ALIGNMENT_POWER
+        else:
+            nodes[at][kind] = offset >> ALIGNMENT_POWER
+
+    for key, value in records:
+        offset = first_record + len(body)
+        idx = bucket_index(key, bucket_number)
+        chain = ("bucket", idx)
+        current = buckets[idx]
+        while current:
+            node_offset = current << ALIGNMENT_POWER
+            node = nodes[node_offset]
+            side = 1 if order(key) > order(node[0]) else 2
+            chain = (side, node_offset)
+            current = node[side]
+        link(chain, offset)
+
+        nodes[offset] = [key, 0, 0, compress(value)]
+        size = 1 + 1 + 4 + 4 + 2 + len(vnum(len(key))) + len(vnum(len(nodes[offset][3])))
+        size += len(key) + len(nodes[offset][3])
+        padding = (align - size % align) % align
+        body += bytes(size + padding)
+        nodes[offset].append(padding)
+
+    for offset, (key, left, right, data, padding) in nodes.items():
+        record = struct.pack("<BBIIH", 0xC8, hash_value(key), left, right, padding)
+        record += vnum(len(key)) + vnum(len(data)) + key + data + bytes(padding)
+        start = offset - first_record
+        body[start:start + len(record)] = record
+
+    file_size = first_record + len(body)
+    magic = b"ToKyO CaBiNeT\n1.0:911\n".ljust(32, b"\0")
+    header = magic + struct.pack("<BBBBB3x", 0, 0, ALIGNMENT_POWER, FREE_BLOCK_POOL_POWER, options)
+    header += struct.pack("<QQQQ", bucket_number, len(nodes), file_size, first_record)
+    header = header.ljust(HEADER_SIZE, b"\0")
+
+    data = header + struct.pack("<%dI" % bucket_number, *buckets)
+    data = data.ljust(first_record, b"\0") + body
+    with open(path, "wb") as f:
+        f.write(data)
+
+
+if __name__ == "__main__":
+    here = os.path.dirname(os.path.abspath(__file__))
+    build(
+        os.path.join(here, "casket-deflate.tch"),
+        compressed_records(),
+        options=OPTION_DEFLATE,
+        compress=raw_deflate,
+    )
+    build(
+        os.path.join(here, "casket-bzip.tch"),
+        compressed_records(),
+        options=OPTION_BZIP,
+        compress=bz2.compress,
+    )
+    build(os.path.join(here, "casket-order.tch"), order_records(), bucket_number=1)
@@ -0,0 +1,43 @@
+#!/bin/sh
+# Create databases by tokyo cabinet itself, which have the same records as the ones built
+# by build_fixtures.py. The tests reading them are ignored by default:
+#
+#     tests/fixtures/create_with_tchmgr.sh
+#     cargo test -- --ignored
+set -eu
+cd "$(dirname "$0")"
+
+hex() {
+    od -An -v -tx1 | tr -d ' \n'
+}
+
+for option in d b t; do
+    path="casket-tc-$option.tch"
+    rm -f "$path"
+    tchmgr create "-t$option" "$path" 1031
+    i=0
+    while [ "$i" -lt 100 ]; do
+        key=$(printf 'key%d' "$i" | hex)
+        value=""
+        j=0
+        while [ "$j" -le $((i % 7)) ]; do
+            value="$value$(printf 'value%d ' "$i" | hex)"
+            j=$((j + 1))
+        done
+        j=0
+        while [ "$j" -lt $((i % 50)) ]; do
+            value="$value$(printf '%02x' "$j")"
+            j=$((j + 1))
+        done
+        tchmgr put -sx "$path" "$key" "$value"
+        i=$((i + 1))
+    done
+done
+
+# the same records as casket-order.tch
+path=casket-tc-order.tch
+rm -f "$path"
+tchmgr create "$path" 1
+for key in a0316 z1 a0689 z2 a0596 z3; do
+    tchmgr put "$path" "$key" "value of $key"
+done
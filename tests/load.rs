@@ -0,0 +1,89 @@
+mod common;
+
+use std::{fs, io::Cursor};
+
+use common::{small, TempDir};
+use tchread::{load, Error, Warning};
+
+/// casket.tch with `bytes` written at `offset`
+fn patched_casket(offset: usize, bytes: &[u8]) -> Cursor<Vec<u8>> {
+    let mut data = fs::read("casket.tch").unwrap();
+    data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    Cursor::new(data)
+}
+
+#[test]
+fn reject_invalid_header_parameters() {
+    for (offset, bytes, field) in [
+        (40, &[0u8; 8][..], "bucket number"),
+        (34, &[64], "alignment power"),
+        (35, &[64], "free block pool power"),
+    ] {
+        let reader = patched_casket(offset, bytes);
+        match load::load_with_endian(reader, binrw::Endian::Little) {
+            Err(Error::InvalidHeader { field: f, .. }) => assert_eq!(f, field),
+            Err(e) => panic!("unexpected error for {}: {}", field, e),
+            Ok(_) => panic!("{} is not rejected", field),
+        }
+    }
+}
+
+/// casket.tch whose root record of the first bucket links itself by the left chain
+fn cyclic_casket() -> Cursor<Vec<u8>> {
+    let data = fs::read("casket.tch").unwrap();
+    let root = u32::from_le_bytes(data[256..260].try_into().unwrap());
+    let offset = (root as usize) << data[34];
+    // skip the magic number and the hash value
+    patched_casket(offset + 2, &root.to_le_bytes())
+}
+
+#[test]
+fn detect_cycles_of_chains() {
+    let reader = cyclic_casket();
+    let mut tchdb = small(load::load_with_endian(reader, binrw::Endian::Little).unwrap());
+
+    assert!(matches!(
+        tchdb.dump_bucket(0),
+        Err(Error::ChainCycle { .. })
+    ));
+
+    let cycles = (0..1000)
+        .map(|i| tchdb.get(format!("key{}", i)))
+        .filter(|r| matches!(r, Err(Error::ChainCycle { .. })))
+        .count();
+    assert!(cycles > 0);
+}
+
+#[test]
+fn warn_pending_wal_on_opening_path() {
+    let dir = TempDir::new("pending-wal");
+    let path = dir.copy("casket.tch");
+    assert!(load::open(&path).unwrap().warnings().is_empty());
+
+    // the file size and the entry of the header, as tokyo cabinet logs first
+    let mut wal = fs::read("casket.tch").unwrap()[56..64].to_vec();
+    wal.extend_from_slice(&0u64.to_le_bytes());
+    wal.extend_from_slice(&256u32.to_le_bytes());
+    wal.extend_from_slice(&fs::read("casket.tch").unwrap()[..256]);
+    fs::write(dir.join("casket.tch.wal"), wal).unwrap();
+
+    let warnings = load::open(&path).unwrap().warnings();
+    assert_eq!(warnings, vec![Warning::PendingWal]);
+    assert!(matches!(
+        load::open(&path).unwrap().strict(),
+        Err(Error::Unclean(_))
+    ));
+}
+
+#[test]
+fn reject_value_size_beyond_file() {
+    // the value size of the record at 0x12c0 is patched to `u32::MAX`
+    let reader = patched_casket(0x12cd, &[0x80, 0x80, 0x80, 0x80, 0x0f]);
+    let mut tchdb = small(load::load_with_endian(reader, binrw::Endian::Little).unwrap());
+
+    let error = tchdb
+        .read_record_spaces(true)
+        .find_map(|record| record.err())
+        .expect("the corrupt value size is not detected");
+    assert!(matches!(error, Error::CorruptRecord { offset: 0x12c0, .. }));
+}
@@ -0,0 +1,58 @@
+//! Locks of other processes are simulated by open file description locks, which conflict with
+//! `fcntl` locks even in the same process
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::{fs::File, io, os::unix::io::AsRawFd};
+
+use common::TempDir;
+use tchread::{load, Error, LockMode};
+
+/// Lock or unlock the whole file by an open file description lock, without waiting
+fn try_lock(file: &File, lock_type: i32) -> io::Result<()> {
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = lock_type as _;
+    lock.l_whence = libc::SEEK_SET as _;
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_OFD_SETLK, &lock) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[test]
+fn hold_shared_lock_until_drop() {
+    let dir = TempDir::new("lock");
+    let path = dir.copy("casket.tch");
+    // keep the file open while the database is open, closing it would release the lock
+    let writer = File::options().read(true).write(true).open(&path).unwrap();
+
+    let tchdb = load::open_locked(&path, LockMode::NonBlocking).unwrap();
+    let error = try_lock(&writer, libc::F_WRLCK).unwrap_err();
+    assert!(matches!(
+        error.raw_os_error(),
+        Some(libc::EAGAIN | libc::EACCES)
+    ));
+    // other readers can share the lock
+    try_lock(&writer, libc::F_RDLCK).unwrap();
+    try_lock(&writer, libc::F_UNLCK).unwrap();
+
+    drop(tchdb);
+    try_lock(&writer, libc::F_WRLCK).unwrap();
+}
+
+#[test]
+fn fail_without_waiting_for_writers() {
+    let dir = TempDir::new("lock-nonblocking");
+    let path = dir.copy("casket.tch");
+    let writer = File::options().read(true).write(true).open(&path).unwrap();
+    try_lock(&writer, libc::F_WRLCK).unwrap();
+
+    assert!(matches!(
+        load::open_locked(&path, LockMode::NonBlocking),
+        Err(Error::Locked)
+    ));
+
+    try_lock(&writer, libc::F_UNLCK).unwrap();
+    load::open_locked(&path, LockMode::NonBlocking).unwrap();
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use std::{borrow::Cow, fs, io::Cursor};
+
+use common::{records, small};
+use tchread::{load, Error};
+
+#[test]
+fn borrow_values() {
+    let expected = records(&mut small(load::open("casket.tch").unwrap()));
+    let tchdb = small(unsafe { load::open_mmap("casket.tch") }.unwrap());
+
+    for (key, value) in &expected {
+        match tchdb.get_ref(key).unwrap() {
+            Some(Cow::Borrowed(borrowed)) => assert_eq!(borrowed, value, "{:?}", key),
+            other => panic!("{:?} for {:?}", other, key),
+        }
+    }
+    assert_eq!(tchdb.get_ref("missing").unwrap(), None);
+}
+
+#[test]
+fn decompress_values() {
+    let path = "tests/fixtures/casket-deflate.tch";
+    let mut reader = small(load::open(path).unwrap());
+    let tchdb = small(unsafe { load::open_mmap(path) }.unwrap());
+
+    for i in 0..100 {
+        let key = format!("key{}", i);
+        match tchdb.get_ref(&key).unwrap() {
+            Some(Cow::Owned(value)) => assert_eq!(Some(value), reader.get(&key).unwrap()),
+            other => panic!("{:?} for {}", other, key),
+        }
+    }
+    assert_eq!(tchdb.get_ref("key100").unwrap(), None);
+}
+
+#[test]
+fn reject_bucket_array_beyond_end_of_file() {
+    for bucket_number in [0x1000u64, 1 << 62, u64::MAX] {
+        let mut data = fs::read("casket.tch").unwrap();
+        data[40..48].copy_from_slice(&bucket_number.to_le_bytes());
+        let tchdb =
+            small(load::load_with_endian(Cursor::new(data), binrw::Endian::Little).unwrap());
+
+        assert!(
+            matches!(tchdb.bucket_slice(), Err(Error::CorruptBucketArray(_))),
+            "{:#x}",
+            bucket_number
+        );
+        assert!(
+            matches!(tchdb.get_ref("pinnyu"), Err(Error::CorruptBucketArray(_))),
+            "{:#x}",
+            bucket_number
+        );
+    }
+}
@@ -0,0 +1,20 @@
+mod common;
+
+use std::{fs, io::Cursor};
+
+use common::{small, TempDir};
+use tchread::load;
+
+#[test]
+fn keep_opaque_region() {
+    let mut data = fs::read("casket.tch").unwrap();
+    data[128..256].copy_from_slice(&(0..128).collect::<Vec<u8>>());
+    let opaque = data[128..256].to_vec();
+    let mut tchdb = small(load::load(Cursor::new(data)).unwrap());
+
+    let dir = TempDir::new("optimize");
+    let path = dir.join("optimized.tch");
+    let optimized = tchdb.optimize(&tchdb.optimize_builder(), &path).unwrap();
+    assert_eq!(optimized.header().opaque_region, opaque);
+    assert_eq!(fs::read(&path).unwrap()[128..256], opaque);
+}
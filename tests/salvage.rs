@@ -0,0 +1,98 @@
+mod common;
+
+use std::{collections::BTreeMap, fs, io::Cursor, path::Path};
+
+use common::{records, small, TempDir};
+use tchread::{binrw_types::RecordSpace, load, Salvaged, TCHDBBuilder};
+
+type Records = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// Records and skipped regions found by salvaging `data`
+fn salvage(data: Vec<u8>) -> (Records, Vec<(u64, u64)>) {
+    let mut tchdb = small(load::load(Cursor::new(data)).unwrap());
+    let mut records = BTreeMap::new();
+    let mut skipped = Vec::new();
+    for salvaged in tchdb.salvage().unwrap() {
+        match salvaged.unwrap() {
+            Salvaged::Record(record) => {
+                records.insert(record.key, record.value.into_value().into_value());
+            }
+            Salvaged::Skipped { offset, size } => skipped.push((offset, size)),
+        }
+    }
+    (records, skipped)
+}
+
+/// Offsets of records in the file order, with their keys
+fn record_offsets(path: &Path) -> Vec<(u64, Vec<u8>)> {
+    let mut tchdb = small(load::open(path).unwrap());
+    tchdb
+        .read_record_spaces(false)
+        .filter_map(|record| match record.unwrap() {
+            RecordSpace::Record(record) => Some((record.space_offset(), record.key)),
+            RecordSpace::FreeBlock(_) => None,
+        })
+        .collect()
+}
+
+#[test]
+fn recover_records_after_corrupt_record() {
+    let offsets = record_offsets(Path::new("casket.tch"));
+    let (offset, key) = &offsets[offsets.len() / 2];
+    let next = offsets[offsets.len() / 2 + 1].0;
+
+    let mut data = fs::read("casket.tch").unwrap();
+    let mut expected = records(&mut small(load::load(Cursor::new(data.clone())).unwrap()));
+    expected.remove(key);
+    // break the magic number of a record in the middle of the file
+    data[*offset as usize] = 0;
+
+    let (records, skipped) = salvage(data);
+    assert_eq!(records, expected);
+    assert_eq!(skipped, vec![(*offset, next - offset)]);
+}
+
+#[test]
+fn skip_to_record_without_reading_its_value() {
+    let dir = TempDir::new("salvage");
+    // the bytes of a valid record to embed in a value
+    let fake_path = dir.join("fake.tch");
+    TCHDBBuilder::new()
+        .bucket_number(1)
+        .create(&fake_path)
+        .unwrap();
+    small(load::open_writable(&fake_path).unwrap())
+        .put("fake", "x")
+        .unwrap();
+    let fake = fs::read(&fake_path).unwrap()[record_offsets(&fake_path)[0].0 as usize..].to_vec();
+
+    let path = dir.join("casket.tch");
+    TCHDBBuilder::new().bucket_number(1).create(&path).unwrap();
+    let mut tchdb = small(load::open_writable(&path).unwrap());
+    tchdb.put("first", "a").unwrap();
+    // the value of "outer" starts at 19 bytes from the aligned record, so the embedded
+    // record starts at an aligned offset
+    let mut value = vec![0; 13];
+    value.extend_from_slice(&fake);
+    value.resize(100, 0);
+    tchdb.put("outer", &value).unwrap();
+    tchdb.put("last", "z").unwrap();
+    drop(tchdb);
+
+    let offsets = record_offsets(&path);
+    let embedded = offsets[1].0 + 32;
+    let mut data = fs::read(&path).unwrap();
+    assert_eq!(data[embedded as usize..][..fake.len()], fake);
+    // break the record before "outer"
+    data[offsets[0].0 as usize] = 0;
+
+    let (records, skipped) = salvage(data);
+    assert_eq!(
+        records,
+        BTreeMap::from([
+            (b"outer".to_vec(), value),
+            (b"last".to_vec(), b"z".to_vec())
+        ])
+    );
+    assert_eq!(skipped, vec![(offsets[0].0, offsets[1].0 - offsets[0].0)]);
+}
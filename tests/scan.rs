@@ -0,0 +1,96 @@
+mod common;
+
+use std::process::Command;
+
+use common::small;
+use tchread::{binrw_types::RecordSpace, load};
+
+type Scanned = (Vec<(Vec<u8>, Vec<u8>)>, usize);
+
+/// Records sorted by keys and the number of free blocks, keeping duplicates if any
+fn serial_scan(path: &str) -> Scanned {
+    let mut tchdb = small(load::open(path).unwrap());
+    let mut records = Vec::new();
+    let mut free_blocks = 0;
+    for record_space in tchdb.read_record_spaces(true) {
+        match record_space.unwrap() {
+            RecordSpace::Record(record) => {
+                records.push((record.key, record.value.into_value().into_value()))
+            }
+            RecordSpace::FreeBlock(_) => free_blocks += 1,
+        }
+    }
+    records.sort();
+    (records, free_blocks)
+}
+
+fn parallel_scan(path: &str, jobs: usize) -> Scanned {
+    let tchdb = small(load::open_concurrent(path).unwrap());
+    let states = tchdb
+        .scan_parallel(
+            jobs,
+            true,
+            || (Vec::new(), 0),
+            |(records, free_blocks), record_space| {
+                match record_space {
+                    RecordSpace::Record(record) => {
+                        records.push((record.key, record.value.into_value().into_value()))
+                    }
+                    RecordSpace::FreeBlock(_) => *free_blocks += 1,
+                }
+                Ok(())
+            },
+        )
+        .unwrap();
+    assert!(states.len() <= jobs.max(1));
+
+    let mut records: Vec<_> = states.iter().flat_map(|(r, _)| r.clone()).collect();
+    records.sort();
+    (records, states.iter().map(|(_, f)| f).sum())
+}
+
+#[test]
+fn same_records_as_serial_scan() {
+    for path in [
+        "casket.tch",
+        "casket-with-free-space.tch",
+        "tests/fixtures/casket-deflate.tch",
+    ] {
+        let expected = serial_scan(path);
+        assert!(!expected.0.is_empty());
+        // casket.tch has only 2 buckets and 28 records
+        for jobs in [0, 1, 2, 3, 8, 64, 1000] {
+            assert_eq!(
+                parallel_scan(path, jobs),
+                expected,
+                "{} with {} jobs",
+                path,
+                jobs
+            );
+        }
+    }
+}
+
+#[test]
+fn list_with_jobs() {
+    let list = |jobs: usize| {
+        let output = Command::new(env!("CARGO_BIN_EXE_rs-tchread"))
+            .args(["list", "--pv", "--jobs", &jobs.to_string(), "casket.tch"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let mut lines: Vec<_> = output
+            .stdout
+            .split(|&b| b == b'\n')
+            .map(<[u8]>::to_vec)
+            .collect();
+        lines.sort();
+        lines
+    };
+
+    let expected = list(1);
+    assert_eq!(expected.len(), 28 + 1);
+    for jobs in [2, 3, 100] {
+        assert_eq!(list(jobs), expected, "{} jobs", jobs);
+    }
+}
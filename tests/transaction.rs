@@ -0,0 +1,73 @@
+mod common;
+
+use std::fs::{self, File};
+
+use common::{check_records, records, small, TempDir};
+use tchread::{load, wal, Error, TCHDBBuilder};
+
+#[test]
+fn log_clean_header_and_abort() {
+    let dir = TempDir::new("transaction");
+    let path = dir.copy("casket.tch");
+    let original = fs::read(&path).unwrap();
+
+    let mut tchdb = small(load::open_writable(&path).unwrap());
+    tchdb.tran_begin().unwrap();
+    tchdb.put("pinnyu", "splatoon3").unwrap();
+    tchdb.put("new key", "new value").unwrap();
+
+    // the file size, then the offset and the size of the first entry precede the header
+    let log = fs::read(wal::wal_path(&path)).unwrap();
+    assert_eq!(&log[20..276], &original[..256]);
+    assert_eq!(fs::read(&path).unwrap()[33] & 0x01, 0x01);
+
+    tchdb.tran_abort().unwrap();
+    assert_eq!(fs::read(&path).unwrap(), original);
+    assert!(!wal::wal_path(&path).exists());
+}
+
+#[test]
+fn commit_and_remove_log() {
+    let dir = TempDir::new("transaction-commit");
+    let path = dir.copy("casket.tch");
+
+    let mut tchdb = small(load::open_writable(&path).unwrap());
+    let mut expected = records(&mut tchdb);
+    tchdb.tran_begin().unwrap();
+    for (key, value) in [("pinnyu", "splatoon3"), ("new key", "new value")] {
+        tchdb.put(key, value).unwrap();
+        expected.insert(key.into(), value.into());
+    }
+    tchdb.tran_commit().unwrap();
+    drop(tchdb);
+
+    assert!(!wal::wal_path(&path).exists());
+    assert_eq!(fs::read(&path).unwrap()[33] & 0x01, 0);
+    check_records(&path, &expected);
+}
+
+#[test]
+fn empty_log_is_committed() {
+    let dir = TempDir::new("transaction-empty-log");
+    let path = dir.copy("casket.tch");
+    // left by a writer which crashed after emptying the log at the commit
+    fs::write(wal::wal_path(&path), []).unwrap();
+
+    assert!(!wal::is_pending(&path).unwrap());
+    assert_eq!(wal::open(&path).unwrap(), None);
+}
+
+#[test]
+fn log_next_to_opened_path() {
+    let dir = TempDir::new("transaction-path");
+    let path = dir.join("created.tch");
+    let mut tchdb = small(TCHDBBuilder::new().create(&path).unwrap());
+    tchdb.tran_begin().unwrap();
+    assert!(wal::wal_path(&path).exists());
+    tchdb.tran_abort().unwrap();
+
+    // without the path, the log can't be created
+    let file = File::options().read(true).write(true).open(&path).unwrap();
+    let mut tchdb = small(load::load(file).unwrap());
+    assert!(matches!(tchdb.tran_begin(), Err(Error::UnknownPath)));
+}
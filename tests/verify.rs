@@ -0,0 +1,50 @@
+mod common;
+
+use std::{fs, io::Cursor, path::Path, process::Command};
+
+use common::{small, TempDir};
+use tchread::{load, Finding};
+
+#[test]
+fn check_pool_after_corrupt_record_space() {
+    let mut data = fs::read("casket.tch").unwrap();
+    // break the magic number of the first record, before the free block at 0x1520
+    data[0x1150] = 0;
+    let mut tchdb =
+        small(load::load_with_endian(Cursor::new(data), binrw::Endian::Little).unwrap());
+
+    let findings = tchdb.verify().unwrap();
+    assert!(matches!(
+        findings[0],
+        Finding::CorruptRecordSpace { offset: 0x1150, .. }
+    ));
+    assert!(!findings
+        .iter()
+        .any(|f| matches!(f, Finding::InvalidPoolElement { .. })));
+}
+
+#[test]
+fn exit_status_of_check() {
+    let check = |path: &Path| {
+        Command::new(env!("CARGO_BIN_EXE_rs-tchread"))
+            .arg("check")
+            .arg(path)
+            .output()
+            .unwrap()
+    };
+    let output = check(Path::new("casket.tch"));
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+
+    let dir = TempDir::new("check");
+    let path = dir.copy("casket.tch");
+    let mut data = fs::read(&path).unwrap();
+    data[0x1150] = 0;
+    fs::write(&path, data).unwrap();
+    let output = check(&path);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(!output.stdout.is_empty());
+
+    let output = check(&dir.join("missing.tch"));
+    assert_eq!(output.status.code(), Some(1));
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use std::{
+    fs::{self, File},
+    io::{Cursor, Read},
+    path::PathBuf,
+    process::Command,
+};
+
+use common::{check_records, records, small, TempDir};
+use tchread::{
+    binrw_types::WalEntry,
+    load,
+    wal::{self, Wal, WalOverlay},
+};
+
+/// Copy the database and the log in the middle of a transaction, as if the writer crashed
+fn crashed(dir: &TempDir) -> PathBuf {
+    let path = dir.copy("casket.tch");
+    let mut tchdb = small(load::open_writable(&path).unwrap());
+    tchdb.tran_begin().unwrap();
+    // overwrite in place, append, relink and free records
+    tchdb.put("pinnyu", "sated").unwrap();
+    tchdb.put("pinnyu", "splatoon3".repeat(10)).unwrap();
+    for i in 0..20 {
+        tchdb.put(format!("new{}", i), "value").unwrap();
+    }
+    tchdb.out("shuichi").unwrap();
+
+    let crashed = dir.join("crashed.tch");
+    fs::copy(&path, &crashed).unwrap();
+    fs::copy(wal::wal_path(&path), wal::wal_path(&crashed)).unwrap();
+    tchdb.tran_abort().unwrap();
+    crashed
+}
+
+#[test]
+fn overlay_original_bytes() {
+    let dir = TempDir::new("wal-overlay");
+    let crashed = crashed(&dir);
+    let original = fs::read("casket.tch").unwrap();
+    assert_ne!(fs::read(&crashed).unwrap(), original);
+    assert!(wal::is_pending(&crashed).unwrap());
+
+    let mut overlay =
+        WalOverlay::new(File::open(&crashed).unwrap(), wal::open(&crashed).unwrap()).unwrap();
+    let mut data = Vec::new();
+    overlay.read_to_end(&mut data).unwrap();
+    assert_eq!(data, original);
+}
+
+#[test]
+fn apply_entries_in_reverse() {
+    let current = vec![0xff; 64];
+    let wal = Wal {
+        file_size: 48,
+        entries: vec![
+            WalEntry {
+                offset: 8,
+                data: vec![1; 16],
+            },
+            // logged again after the first modification, which must not win
+            WalEntry {
+                offset: 8,
+                data: vec![2; 16],
+            },
+            // overlaps the end of the first entry
+            WalEntry {
+                offset: 16,
+                data: vec![3; 16],
+            },
+            WalEntry {
+                offset: 0,
+                data: vec![4; 4],
+            },
+        ],
+        truncated: false,
+    };
+
+    let mut overlay = WalOverlay::new(Cursor::new(current), Some(wal)).unwrap();
+    let mut data = Vec::new();
+    overlay.read_to_end(&mut data).unwrap();
+
+    let mut expected = [vec![4; 4], vec![0xff; 4], vec![1; 16], vec![3; 8]].concat();
+    expected.resize(48, 0xff);
+    assert_eq!(data, expected);
+}
+
+#[test]
+fn recover_clean_database() {
+    let dir = TempDir::new("wal-recover");
+    let crashed = crashed(&dir);
+    let expected = records(&mut small(load::open("casket.tch").unwrap()));
+    // the interrupted writer left `HDBFOPEN` set
+    assert_eq!(fs::read(&crashed).unwrap()[33] & 0x01, 0x01);
+    // as if the database was already unclean before the transaction, the flag in the header
+    // logged after the file size, the offset and the size must be cleared too
+    let mut log = fs::read(wal::wal_path(&crashed)).unwrap();
+    log[20 + 33] |= 0x01;
+    fs::write(wal::wal_path(&crashed), log).unwrap();
+
+    let recovered = dir.join("recovered.tch");
+    let status = Command::new(env!("CARGO_BIN_EXE_rs-tchread"))
+        .arg("recover-wal")
+        .arg(&crashed)
+        .arg(&recovered)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let data = fs::read(&recovered).unwrap();
+    assert_eq!(data[33] & 0x01, 0);
+    assert_eq!(data, fs::read("casket.tch").unwrap());
+    check_records(&recovered, &expected);
+
+    // neither the database nor the log is modified
+    assert!(wal::is_pending(&crashed).unwrap());
+    let mut recovered_again = Vec::new();
+    assert!(wal::recover(&crashed, &mut recovered_again).unwrap());
+    assert_eq!(recovered_again, data);
+}
@@ -0,0 +1,203 @@
+mod common;
+
+use std::{
+    fs,
+    io::{Read, Seek},
+    process::Command,
+};
+
+use common::{check_records, records, small, TempDir};
+use tchread::{
+    binrw_types::{FreeBlockPoolElement, U32orU64},
+    load, TCHDB,
+};
+
+#[test]
+fn overwrite_in_place() {
+    let dir = TempDir::new("overwrite-in-place");
+    let path = dir.copy("casket.tch");
+    let size = fs::metadata(&path).unwrap().len();
+
+    let mut tchdb = small(load::open_writable(&path).unwrap());
+    let mut expected = records(&mut tchdb);
+    // "sated" is followed by 7 bytes of padding
+    for (key, value) in [("pinnyu", "sated, sated"), ("shuichi", "s")] {
+        tchdb.put(key, value).unwrap();
+        expected.insert(key.into(), value.into());
+    }
+    drop(tchdb);
+
+    assert_eq!(fs::metadata(&path).unwrap().len(), size);
+    check_records(&path, &expected);
+}
+
+#[test]
+fn overwrite_by_appending() {
+    let dir = TempDir::new("overwrite-by-appending");
+    let path = dir.copy("casket.tch");
+    let size = fs::metadata(&path).unwrap().len();
+
+    let mut tchdb = small(load::open_writable(&path).unwrap());
+    let mut expected = records(&mut tchdb);
+    let value = "splatoon".repeat(10);
+    tchdb.put("pinnyu", &value).unwrap();
+    expected.insert("pinnyu".into(), value.into());
+    drop(tchdb);
+
+    // the old record is left as a free block
+    let grown = fs::metadata(&path).unwrap().len();
+    assert!(grown > size && grown.is_multiple_of(16));
+    check_records(&path, &expected);
+}
+
+#[test]
+fn append_new_records() {
+    let dir = TempDir::new("append-new-records");
+    let path = dir.copy("casket.tch");
+
+    let mut tchdb = small(load::open_writable(&path).unwrap());
+    let mut expected = records(&mut tchdb);
+    // many records in the two buckets are linked from both chains of existing records
+    let mut new_records: Vec<(Vec<u8>, Vec<u8>)> = (0..100)
+        .map(|i| (format!("new{}", i).into(), format!("value{}", i).into()))
+        .collect();
+    new_records.push((b"x".to_vec(), b"23".to_vec()));
+    new_records.push((b"\0\t\n\xff".to_vec(), b"binary\0value".to_vec()));
+    for (key, value) in new_records {
+        tchdb.put(&key, &value).unwrap();
+        expected.insert(key, value);
+    }
+    drop(tchdb);
+
+    assert_eq!(expected.len(), 28 + 102);
+    check_records(&path, &expected);
+}
+
+/// Shorter keys are lexicographically greater, but linked as less keys
+fn check_order(path: &str) {
+    let mut tchdb = small(load::open(path).unwrap());
+    for key in ["a0316", "z1", "a0689", "z2", "a0596", "z3"] {
+        let value = format!("value of {}", key).into_bytes();
+        assert_eq!(tchdb.get(key).unwrap(), Some(value));
+    }
+    assert_eq!(tchdb.verify().unwrap(), vec![]);
+}
+
+#[test]
+fn get_keys_ordered_by_length_first() {
+    check_order("tests/fixtures/casket-order.tch");
+}
+
+#[test]
+#[ignore = "needs fixtures created by tests/fixtures/create_with_tchmgr.sh"]
+fn get_keys_ordered_by_tchmgr() {
+    check_order("tests/fixtures/casket-tc-order.tch");
+}
+
+#[test]
+#[ignore = "needs tchmgr of tokyo cabinet"]
+fn readable_by_tchmgr() {
+    let dir = TempDir::new("readable-by-tchmgr");
+    let path = dir.copy("casket.tch");
+
+    let mut tchdb = small(load::open_writable(&path).unwrap());
+    let mut expected = records(&mut tchdb);
+    for i in 0..100 {
+        let (key, value) = (format!("new{}", i), format!("value{}", i).repeat(i % 10));
+        tchdb.put(&key, &value).unwrap();
+        expected.insert(key.into(), value.into());
+    }
+    tchdb.put("pinnyu", "splatoon".repeat(10)).unwrap();
+    expected.insert("pinnyu".into(), "splatoon".repeat(10).into());
+    drop(tchdb);
+
+    let output = Command::new("tchmgr")
+        .arg("list")
+        .arg("-pv")
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let listed: Vec<_> = output
+        .stdout
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let tab = line.iter().position(|&b| b == b'\t').unwrap();
+            (line[..tab].to_vec(), line[tab + 1..].to_vec())
+        })
+        .collect();
+    assert_eq!(listed.len(), expected.len());
+    assert_eq!(
+        listed
+            .into_iter()
+            .collect::<std::collections::BTreeMap<_, _>>(),
+        expected
+    );
+
+    let status = Command::new("tchmgr")
+        .arg("inform")
+        .arg(&path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+/// The number of children of the record in its tree
+fn children<U: U32orU64, R: Read + Seek>(tchdb: &mut TCHDB<U, R>, key: &[u8]) -> usize {
+    let key = tchdb.hash(key);
+    let record = tchdb.get_record(&key).unwrap().unwrap();
+    [record.left_chain, record.right_chain]
+        .iter()
+        .filter(|chain| !chain.is_empty())
+        .count()
+}
+
+#[test]
+fn remove_records_of_each_shape() {
+    let dir = TempDir::new("remove-records");
+    let path = dir.copy("casket.tch");
+
+    let mut tchdb = small(load::open_writable(&path).unwrap());
+    let mut expected = records(&mut tchdb);
+    let mut pool = tchdb.read_free_block_pool().unwrap().0;
+    for n in [0, 1, 2] {
+        let key = expected
+            .keys()
+            .find(|key| children(&mut tchdb, key) == n)
+            .unwrap_or_else(|| panic!("no record has {} children", n))
+            .clone();
+        let record = tchdb.get_record(&tchdb.hash(&key)).unwrap().unwrap();
+        pool.push(FreeBlockPoolElement {
+            offset: record.space_offset(),
+            size: record.space_size(),
+        });
+
+        assert!(tchdb.out(&key).unwrap());
+        assert!(!tchdb.out(&key).unwrap());
+        assert_eq!(tchdb.get(&key).unwrap(), None);
+        expected.remove(&key);
+        check_records(&path, &expected);
+    }
+
+    // the pool is written in the order of offsets by their differences
+    pool.sort();
+    assert_eq!(tchdb.read_free_block_pool().unwrap().0, pool);
+}
+
+#[test]
+fn remove_all_records() {
+    let dir = TempDir::new("remove-all-records");
+    let path = dir.copy("casket.tch");
+
+    let mut tchdb = small(load::open_writable(&path).unwrap());
+    let mut expected = records(&mut tchdb);
+    let keys: Vec<_> = expected.keys().cloned().collect();
+    // alternate keys not to remove records in the order of the trees
+    for key in keys.iter().step_by(2).chain(keys.iter().skip(1).step_by(2)) {
+        assert!(tchdb.out(key).unwrap());
+        expected.remove(key);
+        check_records(&path, &expected);
+    }
+    assert!(tchdb.read_buckets().unwrap().0.iter().all(|b| b.is_empty()));
+}